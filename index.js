@@ -2,6 +2,8 @@ import init, { Simulation, Settings, SimulationResult } from "./simulation.js";
 
 await init();
 let graph;
+let state; // the simulation in progress, stepped one year per frame
+let frame; // the pending animation frame, if the simulation is running
 const config = {
   drinkers: 0.2,
   fat: 0.5,
@@ -21,35 +23,42 @@ const config = {
 export function simulate() {
   console.log("Attempting to simulate");
 
-  let settings = new Settings();
-  settings.set_drinkers(config.drinkers);
-  settings.set_fat(config.fat);
-  settings.set_hard_drugger(config.hard_drugger);
-  settings.set_max_age(config.max_age);
-  settings.set_population(config.population);
-  settings.set_salt(config.salt);
-  settings.set_smokers(config.smokers);
-  settings.set_sugar(config.sugar);
-  settings.set_vapers(config.vapers);
-  settings.set_vitamins(config.vitamins);
-  settings.set_wealth_factor(config.wealth_factor);
-  settings.set_weed_smokers(config.weed_smokers);
-  settings.set_years(config.years);
+  let settings;
+  try {
+    settings = Settings.from_json(JSON.stringify(config));
+  } catch (error) {
+    console.error(error);
+    alert(error);
+    return;
+  }
 
   let simulation = new Simulation(settings);
 
-  let simil = simulation.long();
-  updateTable(simil.get_population_curve());
+  let next;
+  try {
+    next = simulation.try_start();
+  } catch (error) {
+    console.error(error.message);
+    alert(error.message);
+    return;
+  } finally {
+    simulation.free(); // the state keeps its own copy of the settings
+  }
+
+  frame ? cancelAnimationFrame(frame) : null;
+  state ? state.free() : null;
+  state = next;
+  updateTable([]);
 
   graph ? graph.destroy() : null;
 
   graph = new Chart("graph", {
     type: "line",
     data: {
-      labels: simil.get_population_curve().map((_, i) => i),
+      labels: [],
       datasets: [{
         label: 'Population',
-        data: simil.get_population_curve(),
+        data: [],
         fill: false,
         borderColor: 'rgb(75, 192, 192)',
         tension: 0.1,
@@ -105,9 +114,47 @@ export function simulate() {
       }
     }
   });
+  resumeSimulation();
 }
 simulate();
 
+function stepSimulation() {
+  const population = state.step();
+  graph.data.labels.push(graph.data.labels.length);
+  graph.data.datasets[0].data.push(population);
+  graph.update("none");
+  if (state.is_finished()) {
+    frame = null;
+    showSnapshot();
+  } else {
+    frame = requestAnimationFrame(stepSimulation);
+  }
+}
+
+// snapshots are copies on the wasm heap, which JavaScript's garbage collector can't see, so they
+// have to be freed once they have been read
+function showSnapshot() {
+  const snapshot = state.snapshot();
+  updateTable(snapshot.get_population_curve());
+  snapshot.free();
+}
+
+export function pauseSimulation() {
+  frame ? cancelAnimationFrame(frame) : null;
+  frame = null;
+  showSnapshot();
+}
+
+export function resumeSimulation() {
+  if (!frame && !state.is_finished()) {
+    frame = requestAnimationFrame(stepSimulation);
+  }
+}
+
+export function togglePause() {
+  frame ? pauseSimulation() : resumeSimulation();
+}
+
 export function openConfigure() {
   document.getElementById("config-popup").style.display = "flex";
   setTimeout(() => {
@@ -0,0 +1,455 @@
+//! How far the BRNN surrogate can be trusted. The network answers in microseconds, but only
+//! approximates the simulation, and how well depends on where in the space of settings it is
+//! asked. This module runs both on a batch of scenarios and reports:
+//!
+//! - the error of the prediction for every scenario, absolute and relative to the simulation,
+//! - calibration data: the scenarios sorted by prediction and binned, with the mean prediction and
+//!   the mean simulated population of every bin, ready to plot against the diagonal,
+//! - the regions of every setting where the relative error is above a tolerance.
+//!
+//! The report can be saved as JSON, and `AccuracyReport::warning` tells the UI when settings fall
+//! somewhere the prediction shouldn't be trusted.
+
+use crate::brnn::{features, Brnn, FEATURES, FEATURE_NAMES};
+use crate::error::SimulationError;
+use crate::types::{Settings, Simulation};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// The number of equal width regions the checked range of every setting is split into.
+const REGIONS: usize = 5;
+
+/// The fewest scenarios a region needs before it can be called unreliable, so one bad scenario
+/// doesn't condemn a whole region.
+const MIN_REGION_SCENARIOS: usize = 2;
+
+/// The prediction and the simulated population for one scenario.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScenarioAccuracy {
+    pub predicted: f64,
+    pub simulated: f64,
+    pub absolute_error: f64,
+    pub relative_error: f64, // the absolute error divided by the simulated population (or by 1)
+}
+
+/// Scenarios with similar predictions. When the surrogate is calibrated, the mean prediction of
+/// every bin is close to the mean simulated population.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationBin {
+    pub scenarios: usize,
+    pub mean_predicted: f64,
+    pub mean_simulated: f64,
+}
+
+/// A range of one setting where the surrogate is off by more than the tolerance on average.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnreliableRegion {
+    pub setting: String, // one of `FEATURE_NAMES`
+    pub from: f64,
+    pub to: f64,
+    pub scenarios: usize,
+    pub mean_relative_error: f64,
+}
+
+impl UnreliableRegion {
+    fn contains(&self, value: f64) -> bool {
+        (self.from..=self.to).contains(&value)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccuracyReport {
+    tolerance: f64,
+    scenarios: Vec<ScenarioAccuracy>,
+    calibration: Vec<CalibrationBin>,
+    checked_ranges: Vec<(f64, f64)>, // the range of every setting the scenarios covered
+    unreliable: Vec<UnreliableRegion>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl AccuracyReport {
+    /// Runs the simulation and the network for every scenario in a JSON list of settings.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn compare_json(
+        network: &Brnn,
+        scenarios: &str,
+        tolerance: f64,
+    ) -> Result<AccuracyReport, SimulationError> {
+        let scenarios: Vec<Settings> = serde_json::from_str(scenarios)
+            .map_err(|error| SimulationError::invalid("scenarios", error.to_string()))?;
+
+        AccuracyReport::compare(network, &scenarios, tolerance)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn from_json(json: &str) -> Result<AccuracyReport, String> {
+        serde_json::from_str(json).map_err(|error| error.to_string())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_predicted(&self) -> Vec<f64> {
+        self.scenarios.iter().map(|s| s.predicted).collect()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_simulated(&self) -> Vec<f64> {
+        self.scenarios.iter().map(|s| s.simulated).collect()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_absolute_errors(&self) -> Vec<f64> {
+        self.scenarios.iter().map(|s| s.absolute_error).collect()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_relative_errors(&self) -> Vec<f64> {
+        self.scenarios.iter().map(|s| s.relative_error).collect()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_mean_relative_error(&self) -> f64 {
+        let count = self.scenarios.len().max(1) as f64;
+
+        self.scenarios.iter().map(|s| s.relative_error).sum::<f64>() / count
+    }
+
+    /// The x values of the calibration plot, one per bin.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_calibration_predicted(&self) -> Vec<f64> {
+        self.calibration
+            .iter()
+            .map(|bin| bin.mean_predicted)
+            .collect()
+    }
+
+    /// The y values of the calibration plot, one per bin.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_calibration_simulated(&self) -> Vec<f64> {
+        self.calibration
+            .iter()
+            .map(|bin| bin.mean_simulated)
+            .collect()
+    }
+
+    /// The unreliable regions, described for people, e.g. "smokers from 0.8 to 1: 35% error".
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_unreliable_regions(&self) -> Vec<String> {
+        self.unreliable
+            .iter()
+            .map(|region| {
+                format!(
+                    "{} from {} to {}: {:.0}% error",
+                    region.setting,
+                    round(region.from),
+                    round(region.to),
+                    region.mean_relative_error * 100.0
+                )
+            })
+            .collect()
+    }
+
+    /// Why the prediction for these settings shouldn't be trusted, if it shouldn't: because a
+    /// setting is outside what the report checked, or in a region where the surrogate was off.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn warning(&self, settings: &Settings) -> Option<String> {
+        let values = features(settings);
+
+        let mut reasons = Vec::new();
+
+        for (feature, &value) in values.iter().enumerate() {
+            let value = value as f64;
+            let name = FEATURE_NAMES[feature];
+
+            if let Some(&(min, max)) = self.checked_ranges.get(feature) {
+                if value < min || value > max {
+                    reasons.push(format!(
+                        "{} is {}, outside the checked range of {} to {}",
+                        name,
+                        round(value),
+                        round(min),
+                        round(max)
+                    ));
+
+                    continue;
+                }
+            }
+
+            for region in &self.unreliable {
+                if region.setting == name && region.contains(value) {
+                    reasons.push(format!(
+                        "the prediction is off by {:.0}% on average when {} is between {} and {}",
+                        region.mean_relative_error * 100.0,
+                        name,
+                        round(region.from),
+                        round(region.to)
+                    ));
+                }
+            }
+        }
+
+        if reasons.is_empty() {
+            None
+        } else {
+            Some(format!(
+                "The quick prediction may be unreliable: {}.",
+                reasons.join("; ")
+            ))
+        }
+    }
+}
+
+impl AccuracyReport {
+    /// Runs the simulation and the network for every scenario. Regions of a setting where the
+    /// relative error is above `tolerance` on average, e.g. 0.2 for 20%, are reported as
+    /// unreliable.
+    pub fn compare(
+        network: &Brnn,
+        scenarios: &[Settings],
+        tolerance: f64,
+    ) -> Result<AccuracyReport, SimulationError> {
+        let results = scenarios
+            .iter()
+            .map(|settings| {
+                let simulated = Simulation::new(settings.clone()).try_long()?;
+
+                Ok((network.predict(settings), simulated.get_population() as f64))
+            })
+            .collect::<Result<Vec<_>, SimulationError>>()?;
+
+        AccuracyReport::from_results(
+            &scenarios.iter().map(features).collect::<Vec<_>>(),
+            &results,
+            tolerance,
+        )
+    }
+
+    /// Builds a report from runs that were already made, as pairs of the prediction and the
+    /// simulated population, along with the network inputs of every run. There has to be a row
+    /// of inputs for every result.
+    pub fn from_results(
+        inputs: &[[f32; FEATURES]],
+        results: &[(f64, f64)],
+        tolerance: f64,
+    ) -> Result<AccuracyReport, SimulationError> {
+        if inputs.len() != results.len() {
+            return Err(SimulationError::invalid(
+                "inputs",
+                format!(
+                    "should have a row for each of the {} results, got {}",
+                    results.len(),
+                    inputs.len()
+                ),
+            ));
+        }
+
+        let scenarios: Vec<ScenarioAccuracy> = results
+            .iter()
+            .map(|&(predicted, simulated)| {
+                let absolute_error = (predicted - simulated).abs();
+
+                ScenarioAccuracy {
+                    predicted,
+                    simulated,
+                    absolute_error,
+                    relative_error: absolute_error / simulated.abs().max(1.0),
+                }
+            })
+            .collect();
+
+        let checked_ranges: Vec<(f64, f64)> = (0..FEATURES)
+            .map(|feature| {
+                inputs
+                    .iter()
+                    .map(|row| row[feature] as f64)
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+                        (min.min(value), max.max(value))
+                    })
+            })
+            .filter(|_| !inputs.is_empty())
+            .collect();
+
+        let unreliable = checked_ranges
+            .iter()
+            .enumerate()
+            .flat_map(|(feature, &range)| {
+                unreliable_regions(feature, range, inputs, &scenarios, tolerance)
+            })
+            .collect();
+
+        Ok(AccuracyReport {
+            tolerance,
+            calibration: calibration(&scenarios, 10),
+            scenarios,
+            checked_ranges,
+            unreliable,
+        })
+    }
+
+    pub fn scenarios(&self) -> &[ScenarioAccuracy] {
+        &self.scenarios
+    }
+
+    pub fn calibration(&self) -> &[CalibrationBin] {
+        &self.calibration
+    }
+
+    pub fn unreliable_regions(&self) -> &[UnreliableRegion] {
+        &self.unreliable
+    }
+}
+
+/// Sorts the scenarios by prediction, and splits them into `bins` bins of (nearly) equal size.
+fn calibration(scenarios: &[ScenarioAccuracy], bins: usize) -> Vec<CalibrationBin> {
+    let mut sorted: Vec<&ScenarioAccuracy> = scenarios.iter().collect();
+    sorted.sort_by(|a, b| a.predicted.total_cmp(&b.predicted));
+
+    let bins = bins.min(sorted.len());
+
+    (0..bins)
+        .map(|bin| {
+            let members = &sorted[bin * sorted.len() / bins..(bin + 1) * sorted.len() / bins];
+            let count = members.len() as f64;
+
+            CalibrationBin {
+                scenarios: members.len(),
+                mean_predicted: members.iter().map(|s| s.predicted).sum::<f64>() / count,
+                mean_simulated: members.iter().map(|s| s.simulated).sum::<f64>() / count,
+            }
+        })
+        .collect()
+}
+
+/// Splits the checked range of one setting into equal regions, and keeps the ones where the
+/// mean relative error is above the tolerance.
+fn unreliable_regions(
+    feature: usize,
+    (min, max): (f64, f64),
+    inputs: &[[f32; FEATURES]],
+    scenarios: &[ScenarioAccuracy],
+    tolerance: f64,
+) -> Vec<UnreliableRegion> {
+    let width = (max - min) / REGIONS as f64;
+
+    // a setting that was the same in every scenario is one region
+
+    let regions = if width > 0.0 { REGIONS } else { 1 };
+
+    (0..regions)
+        .filter_map(|region| {
+            let from = min + width * region as f64;
+            let to = if region + 1 == regions {
+                max
+            } else {
+                from + width
+            };
+
+            let errors: Vec<f64> = inputs
+                .iter()
+                .zip(scenarios)
+                .filter(|(row, _)| {
+                    let value = row[feature] as f64;
+
+                    // the last region includes its upper end
+
+                    value >= from && (value < to || region + 1 == regions)
+                })
+                .map(|(_, scenario)| scenario.relative_error)
+                .collect();
+
+            let mean_relative_error = errors.iter().sum::<f64>() / errors.len().max(1) as f64;
+
+            (errors.len() >= MIN_REGION_SCENARIOS && mean_relative_error > tolerance).then(|| {
+                UnreliableRegion {
+                    setting: FEATURE_NAMES[feature].to_string(),
+                    from,
+                    to,
+                    scenarios: errors.len(),
+                    mean_relative_error,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Rounds to three decimals, for messages.
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accuracy_report() {
+        // the prediction is good for few smokers and bad for many
+
+        let inputs: Vec<[f32; FEATURES]> = (0..20)
+            .map(|i| {
+                let mut row = features(&Settings::new());
+                row[3] = i as f32 / 19.0;
+                row
+            })
+            .collect();
+
+        let results: Vec<(f64, f64)> = inputs
+            .iter()
+            .map(|row| {
+                let error = if row[3] > 0.8 { 500.0 } else { 10.0 };
+
+                (1000.0 + error, 1000.0)
+            })
+            .collect();
+
+        let report = AccuracyReport::from_results(&inputs, &results, 0.2).unwrap();
+
+        // every result needs its inputs
+
+        assert!(AccuracyReport::from_results(&inputs[1..], &results, 0.2).is_err());
+
+        assert_eq!(report.scenarios().len(), 20);
+        assert_eq!(report.get_absolute_errors()[0], 10.0);
+        assert_eq!(report.calibration().len(), 10);
+        assert!(report
+            .get_calibration_simulated()
+            .iter()
+            .all(|&s| s == 1000.0));
+
+        let regions = report.unreliable_regions();
+
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].setting, "smokers");
+        assert_eq!(regions[0].from, 0.8);
+
+        let mut settings = Settings::new();
+        settings.set_smokers(0.1);
+
+        assert_eq!(report.warning(&settings), None);
+
+        settings.set_smokers(0.9);
+
+        assert!(report.warning(&settings).unwrap().contains("smokers"));
+
+        settings.set_smokers(0.1);
+        settings.set_population(settings.population * 2);
+
+        assert!(report.warning(&settings).unwrap().contains("population"));
+
+        assert_eq!(
+            AccuracyReport::from_json(&report.to_json()).unwrap(),
+            report
+        );
+    }
+}
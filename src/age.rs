@@ -1,103 +1,247 @@
 //! Age generator for Beanreadings, a simulation for population growth.
 //! We use a completely custom approach by me
-
+//!
+//! Every way of generating ages implements `AgeDistribution`, so the simulation can start from
+//! any of them: the custom `AgeGenerator`, the normal `LegacyAgeGenerator`, or an
+//! `EmpiricalAgeDistribution` that samples a real population pyramid.
+
+use crate::bean::Sex;
+use crate::distributions::NormalDistribution;
+use crate::error::SimulationError;
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
-pub struct AgeGenerator {
-    pub median_age: f64,
+/// A distribution of ages (and sexes) for the starting population.
+pub trait AgeDistribution: fmt::Debug + Send + Sync {
+    /// Draws an age.
+    fn sample_age(&self, rng: &mut dyn RngCore) -> u32;
+
+    /// The chance of each age, indexed by age, for the cohort engine. Adds up to 1.
+    fn age_chances(&self) -> Vec<f64>;
+
+    /// Draws an age and a sex. Unless the distribution knows better, half are of each sex.
+    fn sample(&self, rng: &mut dyn RngCore) -> (u32, Sex) {
+        let age = self.sample_age(rng);
+
+        let sex = if rng.gen_bool(0.5) {
+            Sex::Female
+        } else {
+            Sex::Male
+        };
+
+        (age, sex)
+    }
 
-    rng: ThreadRng,
+    /// The chance of each age for beans of one sex, indexed by age. The chances for both sexes
+    /// together add up to 1.
+    fn chances(&self, _sex: Sex) -> Vec<f64> {
+        self.age_chances()
+            .into_iter()
+            .map(|chance| chance * 0.5)
+            .collect()
+    }
 }
 
-#[wasm_bindgen]
+/// The oldest age `AgeGenerator::new` generates, for generators made without a maximum age.
+pub const DEFAULT_MAX_AGE: u32 = 100;
+
+/// The custom age generator. Ages go from 0 to `max_age`, and half of them are below the median
+/// age and half above, for any median between the two.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone)]
+pub struct AgeGenerator {
+    median_age: f64,
+    max_age: u32,
+    pieces: Vec<(f64, f64, f64)>, // spans of years [from, to), and the chance of each
+    table: AliasTable,            // picks a piece
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl AgeGenerator {
-    #[wasm_bindgen(constructor)]
+    /// A generator for ages up to 100. Medians outside of 0 to 100 are moved into it, use
+    /// `try_new` to have them rejected instead.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
     pub fn new(median_age: f64) -> AgeGenerator {
-        AgeGenerator {
-            median_age,
-            rng: rand::thread_rng(),
+        AgeGenerator::bounded(median_age, DEFAULT_MAX_AGE)
+    }
+
+    /// A generator for ages up to `max_age`, which fails if the median can't be reached.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn try_new(median_age: f64, max_age: u32) -> Result<AgeGenerator, SimulationError> {
+        if !median_age.is_finite() || median_age < 0.0 || median_age > max_age as f64 {
+            return Err(SimulationError::invalid(
+                "median_age",
+                format!("should be between 0 and {}, got {}", max_age, median_age),
+            ));
         }
+
+        Ok(AgeGenerator::build(median_age, max_age))
+    }
+
+    /// A generator for ages up to `max_age`, moving the median into 0 to `max_age` if it isn't.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn bounded(median_age: f64, max_age: u32) -> AgeGenerator {
+        let median_age = if median_age.is_nan() { 0.0 } else { median_age };
+
+        AgeGenerator::build(median_age.clamp(0.0, max_age as f64), max_age)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_median_age(&self) -> f64 {
+        self.median_age
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_max_age(&self) -> u32 {
+        self.max_age
     }
 
     pub fn update_median_age(&mut self, median_age: f64) {
-        self.median_age = median_age;
+        *self = AgeGenerator::bounded(median_age, self.max_age);
+    }
+
+    /// The chance of `generate_age` giving each age, indexed by age. This comes from the same
+    /// pieces as `generate_age`, so the cohort engine starts from the same population.
+    pub fn distribution(&self) -> Vec<f64> {
+        let mut chances = vec![0.0; self.max_age as usize + 1];
+
+        for &(from, _, chance) in &self.pieces {
+            chances[from as usize] += chance;
+        }
+
+        chances
     }
+}
 
-    pub fn generate_age(&mut self) -> i32 {
+impl AgeGenerator {
+    fn build(median_age: f64, max_age: u32) -> AgeGenerator {
         // This is a completely custom approach to Age generation by me. We don't use distributions
         // like normal distributions or uniform. We use a custom approach by Aityz :)
 
-        let median_age = self.median_age.round() as i32;
+        let ages = max_age as usize + 1;
+        let mut weights = vec![0.0; ages];
+
+        let mut spread = |from: f64, to: f64, chance: f64| {
+            let from = from.max(0.0) as usize;
+            let to = (to.max(0.0) as usize).min(ages - 1);
+
+            for weight in &mut weights[from..=to] {
+                *weight += chance / (to - from + 1) as f64;
+            }
+        };
 
         // generally if you look at the current curves, most are around working class but if we
         // center on the median age, i can say around 40% of the population is within 10 years of
         // the median age
 
-        let rng = &mut self.rng;
+        spread((median_age - 10.0).ceil(), (median_age + 10.0).floor(), 0.4);
 
-        let chance = rng.gen_bool(0.4);
+        // 15% of the rest are minors, regardless of the median age. This is to normalize the
+        // population. Everyone else is an adult of any age. If the oldest age is below 18,
+        // everyone is a minor anyway.
 
-        if chance {
-            // we have got the age in the 20 year range, but we will randomly select a possible age
-            // within this range
+        spread(0.0, 17.0, 0.6 * 0.15);
 
-            rng.gen_range(median_age - 10..=median_age + 10)
-        } else {
-            // we are in the other 60% of the population.
+        if max_age >= 18 {
+            spread(18.0, max_age as f64, 0.6 * 0.85);
+        }
 
-            // 15% for the chance of being a minor, regardless of the median age. This is to
-            // normalize the population
+        // every age has some weight now. Age `a` covers the years from `a` to `a + 1`, so the
+        // median of the ages is at `median + 0.5` years. We cut the years there, and scale both
+        // sides to half of the population each, so the median always comes out right.
 
-            let chance = rng.gen_bool(0.15);
+        let split = median_age + 0.5;
 
-            if chance {
-                rng.gen_range(0..=17)
+        let mut pieces = Vec::with_capacity(ages + 1);
+
+        for (age, &weight) in weights.iter().enumerate() {
+            let (from, to) = (age as f64, age as f64 + 1.0);
+
+            if from < split && split < to {
+                pieces.push((from, split, weight * (split - from)));
+                pieces.push((split, to, weight * (to - split)));
             } else {
-                // this is 45% chance to be called here, so around half of the population is in
-                // this category. So we split it into half. Peopel who are older than the median
-                // age, and people who are younger than the median age, but are not minors. But
-                // this will only be called if there is a 15 year gap between the median age and
-                // the age of minors.
-
-                if median_age - 15 > 15 {
-                    // median age is above 30, so we call half between the median age and 15
-
-                    let below_median = rng.gen_bool(0.5);
-
-                    if below_median {
-                        rng.gen_range(18..=median_age - 10)
-                    } else {
-                        rng.gen_range(median_age + 10..=100)
-                    }
-                } else {
-                    // we just return median age + 10 to 100, because the median age is below 30
-
-                    rng.gen_range(median_age + 10..=100)
-                }
+                pieces.push((from, to, weight));
             }
         }
+
+        let below: f64 = pieces
+            .iter()
+            .filter(|piece| piece.1 <= split)
+            .map(|piece| piece.2)
+            .sum();
+        let above: f64 = pieces.iter().map(|piece| piece.2).sum::<f64>() - below;
+
+        for piece in &mut pieces {
+            piece.2 *= if piece.1 <= split {
+                0.5 / below
+            } else {
+                0.5 / above
+            };
+        }
+
+        let chances: Vec<f64> = pieces.iter().map(|piece| piece.2).collect();
+
+        AgeGenerator {
+            median_age,
+            max_age,
+            table: AliasTable::new(&chances),
+            pieces,
+        }
+    }
+
+    /// Generates an age, drawing from the simulation's random number generator.
+    pub fn generate_age<R: Rng + ?Sized>(&self, rng: &mut R) -> i32 {
+        let (from, to, _) = self.pieces[self.table.sample(rng)];
+
+        rng.gen_range(from..to).floor() as i32
+    }
+}
+
+impl AgeDistribution for AgeGenerator {
+    fn sample_age(&self, rng: &mut dyn RngCore) -> u32 {
+        self.generate_age(rng).unsigned_abs()
+    }
+
+    fn age_chances(&self) -> Vec<f64> {
+        self.distribution()
     }
 }
 
 // alternative age generator
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone)]
 /// This uses a Normal Distribution to generate ages, which may be less relatistic more faster
-/// because we care about speed.
+/// because we care about speed. Ages above `max_age` are moved down to it.
 pub struct LegacyAgeGenerator {
     pub median_age: f64,
+    pub max_age: u32,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl LegacyAgeGenerator {
-    #[wasm_bindgen(constructor)]
+    /// A generator for ages up to 100.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
     pub fn new(median_age: f64) -> LegacyAgeGenerator {
-        LegacyAgeGenerator { median_age }
+        LegacyAgeGenerator::bounded(median_age, DEFAULT_MAX_AGE)
     }
 
-    pub fn generate_age(&self) -> u32 {
+    /// A generator for ages up to `max_age`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn bounded(median_age: f64, max_age: u32) -> LegacyAgeGenerator {
+        LegacyAgeGenerator {
+            median_age,
+            max_age,
+        }
+    }
+}
+
+impl LegacyAgeGenerator {
+    pub fn generate_age<R: Rng + ?Sized>(&self, rng: &mut R) -> u32 {
         loop {
             // standard deviation is 20 years, because most people live to around 80 years old
 
@@ -107,70 +251,325 @@ impl LegacyAgeGenerator {
 
             let distribution = NormalDistribution::new(self.median_age, 20.0);
 
-            let mut rng = rand::thread_rng();
-
             let number = rng.gen_range(0.0..1.0);
 
-            let age = distribution.quartile(number);
+            let age = distribution.quantile(number);
 
             if age > 0.0 {
-                return age.ceil() as u32;
+                return (age.ceil() as u32).min(self.max_age);
             }
         }
     }
 }
 
-#[wasm_bindgen]
-pub struct NormalDistribution {
-    mean: f64,
-    standard_deviation: f64,
+impl AgeDistribution for LegacyAgeGenerator {
+    fn sample_age(&self, rng: &mut dyn RngCore) -> u32 {
+        self.generate_age(rng)
+    }
+
+    /// Every age `generate_age` can give, which is the normal distribution above 0 rounded up,
+    /// with everything above `max_age` at `max_age`.
+    fn age_chances(&self) -> Vec<f64> {
+        let distribution = NormalDistribution::new(self.median_age, 20.0);
+
+        let above_zero = 1.0 - distribution.cdf(0.0);
+        let oldest = self.max_age as usize;
+
+        let mut chances: Vec<f64> = (0..=oldest)
+            .map(|age| match age {
+                0 => 0.0,
+                age => {
+                    (distribution.cdf(age as f64) - distribution.cdf(age as f64 - 1.0)) / above_zero
+                }
+            })
+            .collect();
+
+        chances[oldest] += (1.0 - distribution.cdf(oldest as f64)) / above_zero;
+
+        chances
+    }
+}
+
+/// A band of ages in a population pyramid, and how many people are in it. The count can be given
+/// for both sexes together (`count`) or for each sex (`female` and `male`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PyramidBand {
+    pub from: u32,
+    pub to: u32, // inclusive
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub female: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub male: Option<f64>,
 }
 
-#[wasm_bindgen]
-impl NormalDistribution {
-    #[wasm_bindgen(constructor)]
-    pub fn new(mean: f64, standard_deviation: f64) -> NormalDistribution {
-        NormalDistribution {
-            mean,
-            standard_deviation,
+impl PyramidBand {
+    /// A band with `count` people, half of each sex.
+    pub fn new(from: u32, to: u32, count: f64) -> PyramidBand {
+        PyramidBand {
+            from,
+            to,
+            count: Some(count),
+            female: None,
+            male: None,
         }
     }
 
-    pub fn pdf(&self, x: f64) -> f64 {
-        // probability density function
+    pub fn by_sex(from: u32, to: u32, female: f64, male: f64) -> PyramidBand {
+        PyramidBand {
+            from,
+            to,
+            count: None,
+            female: Some(female),
+            male: Some(male),
+        }
+    }
 
-        // 1 / (σ * sqrt(2π)) * e^(-1/2 * ((x - μ) / σ)^2)
+    /// The number of women and men in the band.
+    pub fn counts(&self) -> (f64, f64) {
+        match (self.count, self.female, self.male) {
+            (Some(count), _, _) => (count / 2.0, count / 2.0),
+            (None, female, male) => (female.unwrap_or(0.0), male.unwrap_or(0.0)),
+        }
+    }
 
-        1.0 / (self.standard_deviation * (2.0 * std::f64::consts::PI).sqrt())
-            * (-0.5 * ((x - self.mean) / self.standard_deviation).powi(2)).exp()
+    fn ages(&self) -> f64 {
+        (self.to - self.from + 1) as f64
     }
+}
+
+/// A population pyramid: how many people there are in each band of ages. Written in JSON as a
+/// list of bands, in order of age:
+///
+/// ```json
+/// [
+///     { "from": 0, "to": 14, "count": 1800 },
+///     { "from": 15, "to": 64, "female": 3200, "male": 3250 },
+///     { "from": 65, "to": 99, "female": 950, "male": 800 }
+/// ]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<PyramidBand>", into = "Vec<PyramidBand>")]
+pub struct PopulationPyramid {
+    bands: Vec<PyramidBand>,
+}
 
-    pub fn cdf(&self, x: f64) -> f64 {
-        // cumulative density function
+impl PopulationPyramid {
+    /// Checks the bands: they must be in order without overlapping, with counts that are finite
+    /// and not negative, and there must be someone in the pyramid.
+    pub fn new(bands: Vec<PyramidBand>) -> Result<PopulationPyramid, String> {
+        let mut total = 0.0;
 
-        // 1/2 * (1 + erf((x - μ) / (σ * sqrt(2))))
+        for (index, band) in bands.iter().enumerate() {
+            if band.from > band.to {
+                return Err(format!("band {} starts after it ends", index + 1));
+            }
 
-        1f64 / 2f64
-            * (1.0f64
-                + errorfunctions::RealErrorFunctions::erf(
-                    (x - self.mean) / (self.standard_deviation * (2.0f64).sqrt()),
-                ))
+            if index > 0 && band.from <= bands[index - 1].to {
+                return Err(format!(
+                    "band {} overlaps the band before it, bands must be in order of age",
+                    index + 1
+                ));
+            }
+
+            if band.count.is_some() == (band.female.is_some() || band.male.is_some()) {
+                return Err(format!(
+                    "band {} needs either a count, or a female and a male count",
+                    index + 1
+                ));
+            }
+
+            let (female, male) = band.counts();
+
+            if !(female.is_finite() && male.is_finite() && female >= 0.0 && male >= 0.0) {
+                return Err(format!("band {} has an invalid count", index + 1));
+            }
+
+            total += female + male;
+        }
+
+        if total <= 0.0 {
+            return Err("the pyramid is empty".to_string());
+        }
+
+        Ok(PopulationPyramid { bands })
+    }
+
+    pub fn from_json(json: &str) -> Result<PopulationPyramid, String> {
+        serde_json::from_str(json).map_err(|error| error.to_string())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
     }
 
-    pub fn quartile(&self, x: f64) -> f64 {
-        // this is the inverse of the CDF function
+    pub fn bands(&self) -> &[PyramidBand] {
+        &self.bands
+    }
+
+    /// The oldest age in the pyramid.
+    pub fn oldest(&self) -> u32 {
+        self.bands.last().map_or(0, |band| band.to)
+    }
+}
+
+impl TryFrom<Vec<PyramidBand>> for PopulationPyramid {
+    type Error = String;
+
+    fn try_from(bands: Vec<PyramidBand>) -> Result<Self, Self::Error> {
+        PopulationPyramid::new(bands)
+    }
+}
+
+impl From<PopulationPyramid> for Vec<PyramidBand> {
+    fn from(pyramid: PopulationPyramid) -> Self {
+        pyramid.bands
+    }
+}
+
+/// Walker's alias method: after setting up the table once, every draw from a discrete
+/// distribution takes one uniform index and one coin flip, however many outcomes there are.
+#[derive(Debug, Clone)]
+struct AliasTable {
+    chance: Vec<f64>, // the chance of keeping each outcome instead of taking its alias
+    alias: Vec<u32>,
+}
+
+impl AliasTable {
+    /// Builds the table with Vose's method. The weights don't have to add up to 1.
+    fn new(weights: &[f64]) -> AliasTable {
+        let total: f64 = weights.iter().sum();
+        let count = weights.len();
 
-        // μ + σ * sqrt(2) * erf^(-1)(2 * x - 1)
+        let mut scaled: Vec<f64> = weights
+            .iter()
+            .map(|weight| weight * count as f64 / total)
+            .collect();
 
-        self.mean
-            + self.standard_deviation
-                * (2.0f64).sqrt()
-                * errorfunctions::RealErrorFunctions::erfi(2.0 * x - 1.0)
+        let mut chance = vec![1.0; count];
+        let mut alias: Vec<u32> = (0..count as u32).collect();
+
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..count).partition(|&index| scaled[index] < 1.0);
+
+        while let (Some(&less), Some(&more)) = (small.last(), large.last()) {
+            small.pop();
+
+            chance[less] = scaled[less];
+            alias[less] = more as u32;
+
+            scaled[more] -= 1.0 - scaled[less];
+
+            if scaled[more] < 1.0 {
+                large.pop();
+                small.push(more);
+            }
+        }
+
+        // anything left over is 1 up to rounding errors, and keeps itself
+
+        AliasTable { chance, alias }
     }
+
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        let index = rng.gen_range(0..self.chance.len() as u32) as usize;
+
+        if rng.gen_bool(self.chance[index].clamp(0.0, 1.0)) {
+            index
+        } else {
+            self.alias[index] as usize
+        }
+    }
+}
+
+/// Samples ages and sexes from a population pyramid, exactly and in constant time per draw. The
+/// band and sex are picked with the alias method, and the age is uniform within the band.
+#[derive(Debug, Clone)]
+pub struct EmpiricalAgeDistribution {
+    pyramid: PopulationPyramid,
+    table: AliasTable, // two outcomes per band, women first
 }
 
+impl EmpiricalAgeDistribution {
+    pub fn new(pyramid: PopulationPyramid) -> EmpiricalAgeDistribution {
+        let weights: Vec<f64> = pyramid
+            .bands
+            .iter()
+            .flat_map(|band| {
+                let (female, male) = band.counts();
+                [female, male]
+            })
+            .collect();
+
+        EmpiricalAgeDistribution {
+            table: AliasTable::new(&weights),
+            pyramid,
+        }
+    }
+
+    pub fn pyramid(&self) -> &PopulationPyramid {
+        &self.pyramid
+    }
+
+    fn total(&self) -> f64 {
+        self.pyramid
+            .bands
+            .iter()
+            .map(|band| {
+                let (female, male) = band.counts();
+                female + male
+            })
+            .sum()
+    }
+}
+
+impl AgeDistribution for EmpiricalAgeDistribution {
+    fn sample_age(&self, rng: &mut dyn RngCore) -> u32 {
+        self.sample(rng).0
+    }
+
+    fn sample(&self, rng: &mut dyn RngCore) -> (u32, Sex) {
+        let outcome = self.table.sample(rng);
+        let band = &self.pyramid.bands[outcome / 2];
+
+        let sex = [Sex::Female, Sex::Male][outcome % 2];
+
+        (rng.gen_range(band.from..=band.to), sex)
+    }
+
+    fn age_chances(&self) -> Vec<f64> {
+        let female = self.chances(Sex::Female);
+        let male = self.chances(Sex::Male);
+
+        female.iter().zip(&male).map(|(f, m)| f + m).collect()
+    }
+
+    fn chances(&self, sex: Sex) -> Vec<f64> {
+        let total = self.total();
+        let mut chances = vec![0.0; self.pyramid.oldest() as usize + 1];
+
+        for band in &self.pyramid.bands {
+            let (female, male) = band.counts();
+            let count = if sex == Sex::Female { female } else { male };
+
+            for age in band.from..=band.to {
+                chances[age as usize] += count / total / band.ages();
+            }
+        }
+
+        chances
+    }
+}
+
+#[cfg(test)]
 mod test {
     use super::*;
+    use crate::rng::seeded;
 
     #[test]
     fn test_distribution() {
@@ -178,12 +577,14 @@ mod test {
 
         let age_gen = AgeGenerator::new(35.0);
 
+        let mut rng = seeded(35);
+
         // we generate 1000 ages and see the distribution
 
         let mut age_count = vec![0; 101];
 
         for _ in 0..1000 {
-            let age = age_gen.generate_age();
+            let age = age_gen.generate_age(&mut rng);
             age_count[age as usize] += 1;
         }
 
@@ -194,19 +595,110 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_median_age() {
+        let mut rng = seeded(19);
+
+        for (median_age, max_age) in [
+            (0.0, 100),
+            (5.0, 100),
+            (30.5, 100),
+            (95.0, 100),
+            (130.0, 140),
+            (8.0, 10),
+        ] {
+            let age_gen = AgeGenerator::try_new(median_age, max_age).unwrap();
+
+            let mut ages: Vec<i32> = (0..20_001)
+                .map(|_| age_gen.generate_age(&mut rng))
+                .collect();
+            ages.sort();
+
+            assert!(ages[0] >= 0 && ages[20_000] <= max_age as i32);
+
+            // the middle age of the sample, give or take a year of sampling noise
+
+            let median = ages[10_000] as f64;
+
+            assert!(
+                (median - median_age).abs() <= 1.0,
+                "{} {}",
+                median_age,
+                median
+            );
+
+            let chances = age_gen.distribution();
+            let below: f64 = chances.iter().take(median_age.round() as usize).sum();
+
+            assert!((chances.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            assert!(below <= 0.5 + 1e-9 && below + chances[median_age.round() as usize] >= 0.5);
+        }
+
+        assert!(AgeGenerator::try_new(101.0, 100).is_err());
+        assert!(AgeGenerator::try_new(-1.0, 100).is_err());
+        assert!(AgeGenerator::try_new(f64::NAN, 100).is_err());
+    }
+
+    #[test]
+    fn test_empirical_distribution() {
+        let pyramid = PopulationPyramid::from_json(
+            r#"[
+                { "from": 0, "to": 9, "count": 200 },
+                { "from": 10, "to": 19, "female": 300, "male": 100 },
+                { "from": 20, "to": 20, "female": 0, "male": 400 }
+            ]"#,
+        )
+        .unwrap();
+
+        let ages = EmpiricalAgeDistribution::new(pyramid);
+
+        let mut rng = seeded(18);
+        let mut counts = [[0u32; 2]; 3];
+
+        for _ in 0..100_000 {
+            let (age, sex) = ages.sample(&mut rng);
+            let band = (age / 10) as usize;
+
+            counts[band][(sex == Sex::Male) as usize] += 1;
+        }
+
+        // every band and sex gets its share of the pyramid, give or take sampling noise
+
+        let expected = [[0.1, 0.1], [0.3, 0.1], [0.0, 0.4]];
+
+        for (band, shares) in expected.iter().enumerate() {
+            for (sex, share) in shares.iter().enumerate() {
+                let observed = counts[band][sex] as f64 / 100_000.0;
+
+                assert!((observed - share).abs() < 0.01, "{} {}", band, sex);
+            }
+        }
+
+        let chances = ages.age_chances();
+
+        assert!((chances.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_eq!(ages.chances(Sex::Female)[20], 0.0);
+
+        assert!(PopulationPyramid::from_json(r#"[{ "from": 5, "to": 1, "count": 1 }]"#).is_err());
+        assert!(PopulationPyramid::from_json(r#"[{ "from": 0, "to": 1, "count": 0 }]"#).is_err());
+    }
+
     #[test]
     fn test_legacy_distribution() {
         // we recommend using -- --nocapture flag to see the output of the test
 
         let age_gen = LegacyAgeGenerator::new(35.0);
 
+        let mut rng = seeded(35);
+
         // we generate 1000 ages and see the distribution
 
         let mut age_count = vec![0; 101];
 
         for _ in 0..1000 {
-            let age = age_gen.generate_age();
-            age_count[age as usize] += 1;
+            let age = age_gen.generate_age(&mut rng) as usize;
+
+            age_count[age] += 1;
         }
 
         for (i, count) in age_count.iter().enumerate() {
@@ -214,5 +706,18 @@ mod test {
                 println!("Age: {} Count: {}", i, count);
             }
         }
+
+        // an old median piles the ages past the oldest one onto it, and the cohort engine
+        // starts from the same chances
+
+        let age_gen = LegacyAgeGenerator::bounded(90.0, 60);
+
+        assert!((0..1000).all(|_| age_gen.generate_age(&mut rng) <= 60));
+
+        let chances = age_gen.age_chances();
+
+        assert_eq!(chances.len(), 61);
+        assert!((chances.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(chances[60] > 0.9);
     }
 }
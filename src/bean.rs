@@ -2,58 +2,150 @@
 //! set of factors upon initialization. This will be used to determine whether a bean dies of a
 //! certain death or not, or whether it reproduces or not.
 
-use crate::age::AgeGenerator;
+use crate::age::AgeDistribution;
+use crate::causes::CauseOfDeath;
+use crate::distributions::{ContinuousDistribution, TruncatedNormal};
+use crate::exposure::Exposures;
 use crate::factors::*;
+use crate::hazard::RiskFactor;
+use crate::transitions::{remaining_risk, HabitTransitions, DEFAULT_FORMER_RISK_HALF_LIFE};
 use crate::types::Settings;
-use rand::Rng;
+use rand::{Rng, RngCore};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
+/// Beans this old or older can pick up new habits when a policy makes them more common, and beans
+/// born in the simulation draw their habits when they reach it.
+pub const ADULT_AGE: u32 = 18;
+
+/// The standard deviation of sugar, salt and fat intakes around the average in the settings, the
+/// same spread as the ±0.5 uniform ranges they used to be drawn from.
+const DIET_SPREAD: f64 = 0.29;
+
+/// The same for vitamins, which used to be drawn from ±0.6.
+const VITAMIN_SPREAD: f64 = 0.35;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sex {
+    Female,
+    Male,
+}
+
+impl Sex {
+    pub const ALL: [Sex; 2] = [Sex::Female, Sex::Male];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The name of the sex in results and settings.
+    pub fn key(self) -> &'static str {
+        match self {
+            Sex::Female => "female",
+            Sex::Male => "male",
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[derive(Clone, Debug)]
 pub struct Bean {
     age: u32, // beans cant be older than 2.1 billion years
 
+    sex: Sex,
+
     factors: Factors, // this is a structure that represents all of the required factors for a bean
+
+    quit: [Option<u32>; Habit::COUNT], // years since giving up each habit, None if it was never given up
+
+    amounts: Exposures, // how much of each habit the bean uses whenever it has it
+
+    grown_up: bool, // whether the bean has drawn its habits, young beans do when they come of age
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Bean {
-    #[wasm_bindgen(constructor)]
-    pub fn new(context: &Settings, age_gen: &mut AgeGenerator) -> Self {
-        let mut rng = rand::thread_rng();
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_age(&self) -> u32 {
+        self.age
+    }
 
-        let age = age_gen.generate_age().abs() as u32; // using my custom age generator logic
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_sex(&self) -> Sex {
+        self.sex
+    }
 
-        let alcoholism = rng.gen_bool(context.drinkers);
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_factors(&self) -> Factors {
+        self.factors.clone()
+    }
 
-        let smoker = rng.gen_bool(context.smokers);
+    /// Ages the bean by one year. Called once per simulated year for every surviving bean.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn grow_older(&mut self) {
+        self.age += 1;
 
-        let binge_drinkers = rng.gen_bool(context.drinkers % 3.0); // one third of drinkers are binge drinkers
+        if self.factors.smokes_cigarettes {
+            self.factors.exposures.smoke_for_a_year();
+        }
 
-        let smokes_weed = rng.gen_bool(context.weed_smokers);
+        for years in self.quit.iter_mut().flatten() {
+            *years += 1;
+        }
+    }
+}
+
+impl Bean {
+    /// Creates a bean from all of its parts, without sampling anything. Habits it picks up later
+    /// are used in the amounts already in its factors.
+    pub fn from_parts(age: u32, sex: Sex, factors: Factors) -> Self {
+        Bean {
+            age,
+            sex,
+            amounts: factors.exposures,
+            factors,
+            quit: [None; Habit::COUNT],
+            grown_up: true,
+        }
+    }
+
+    /// Creates a bean for the starting population, with an age and sex drawn from `ages`. Beans
+    /// younger than `ADULT_AGE` don't have any habits yet, and draw them when they come of age.
+    pub fn new<R: Rng>(context: &Settings, ages: &dyn AgeDistribution, rng: &mut R) -> Self {
+        let (age, sex) = ages.sample(rng);
+
+        let mut bean = Bean::with_age(context, age, sex, rng);
+
+        if age < ADULT_AGE {
+            bean.wait_for_habits();
+        }
+
+        bean
+    }
+
+    /// Creates a bean of a given age and sex, sampling its factors from the settings.
+    pub fn with_age<R: Rng>(context: &Settings, age: u32, sex: Sex, rng: &mut R) -> Self {
+        // habits are drawn together, so that they go together as often as they do in real life
 
-        let vaper = rng.gen_bool(context.vapers);
+        let habits = context.habit_correlations().sample(context, sex, rng);
 
-        let hard_drugger = rng.gen_bool(context.hard_drugger);
+        // intakes are spread around the average in the settings, and can't go below nothing
 
         let sugar = Sugar {
-            value: rng
-                .gen_range(context.sugar - 0.5..=context.sugar + 0.5)
-                .abs(),
+            value: intake(context.sugar, DIET_SPREAD, rng),
         };
 
         let salt = Salt {
-            value: rng.gen_range(context.salt - 0.5..=context.salt + 0.5).abs(),
+            value: intake(context.salt, DIET_SPREAD, rng),
         };
 
         let fat = Fat {
-            value: rng.gen_range(context.fat - 0.5..=context.fat + 0.5).abs(),
+            value: intake(context.fat, DIET_SPREAD, rng),
         };
 
         let vitamins = Vitamins {
-            value: rng
-                .gen_range(context.vitamins - 0.6..=context.vitamins + 0.6)
-                .abs(),
+            value: intake(context.vitamins, VITAMIN_SPREAD, rng),
         };
 
         // wealth in Context is (upper class - lower class). So, depending on the wealth factor, we
@@ -75,158 +167,294 @@ impl Bean {
             wealth.value = rng.gen_range(0.0..=1.0);
         }
 
+        // amounts are drawn for every bean, for if it ever picks the habit up, but only the habits
+        // it has are used. Smokers have smoked since they were adults
+
+        let amounts = context.doses.sample(rng);
+
+        let mut exposures = amounts;
+
+        for habit in Habit::ALL {
+            if !habits[habit.index()] {
+                exposures.set_amount(habit, 0.0);
+            }
+        }
+
+        if habits[Habit::SmokesCigarettes.index()] {
+            exposures.pack_years = exposures.pack_years_at(age);
+        }
+
         let factors = Factors {
-            alcoholism,
-            binge_drinker: binge_drinkers,
-            smokes_weed,
-            smokes_cigarettes: smoker,
-            smokes_vape: vaper,
-            consumes_hard_drugs: hard_drugger,
+            alcoholism: habits[Habit::Alcoholism.index()],
+            binge_drinker: habits[Habit::BingeDrinker.index()],
+            smokes_weed: habits[Habit::SmokesWeed.index()],
+            smokes_cigarettes: habits[Habit::SmokesCigarettes.index()],
+            smokes_vape: habits[Habit::SmokesVape.index()],
+            consumes_hard_drugs: habits[Habit::ConsumesHardDrugs.index()],
             sugar,
             salt,
             fat,
             vitamins,
             wealth,
+            exposures,
         };
 
-        return Bean { age, factors };
+        Bean {
+            amounts,
+            ..Bean::from_parts(age, sex, factors)
+        }
     }
 
-    #[wasm_bindgen]
-    pub fn get_age(&self) -> u32 {
-        self.age
+    /// Creates a newborn bean. Newborns get a diet and wealth like everyone else, but nobody is
+    /// born smoking or drinking, so all of the recreational habits start out as false until the
+    /// bean comes of age. The sex follows the sex ratio at birth.
+    pub fn newborn<R: Rng>(context: &Settings, rng: &mut R) -> Self {
+        let sex = if rng.gen_bool(context.male_birth_probability()) {
+            Sex::Male
+        } else {
+            Sex::Female
+        };
+
+        let mut bean = Bean::with_age(context, 0, sex, rng);
+
+        bean.wait_for_habits();
+
+        bean
     }
 
-    #[wasm_bindgen]
-    pub fn get_factors(&self) -> Factors {
-        self.factors.clone()
+    /// Takes away the habits of a bean that is too young for them, until it comes of age.
+    fn wait_for_habits(&mut self) {
+        self.factors.clear_habits();
+        self.grown_up = false;
     }
 
-    pub fn dies(&self, context: &Settings) -> bool {
-        // whether this bean dies or not depending on the factors
+    /// Draws the habits of a young bean once it is `ADULT_AGE`, at the rates in the settings then,
+    /// the same way as for the adults in the starting population. Beans that already have their
+    /// habits are left alone. This is how habits get picked up without habit transitions.
+    pub fn come_of_age<R: Rng>(&mut self, context: &Settings, rng: &mut R) {
+        if self.grown_up || self.age < ADULT_AGE {
+            return;
+        }
 
-        if self.age > context.max_age {
-            return true;
-        }
-
-        let mut rng = rand::thread_rng();
-
-        // depending on their habits, they could die
-
-        let mut cardiovascular_disease = 0.0;
-        let mut cancer = 0.0;
-        let mut respiratory_disease = 0.0;
-        let mut digestive_disease = 0.0;
-        let mut infection = 0.0;
-        let mut neonatal = 0.0;
-        let mut dimentia = 0.0;
-        let mut diabetes = 0.0;
-        let mut diarrheal_disease = 0.0;
-        let mut liver_disease = 0.0;
-        let mut kidney_disease = 0.0;
-        let mut malnutrition = 0.0;
-
-        if self.age < 50 {
-            cancer += 0.03;
-            respiratory_disease += 0.02;
-            digestive_disease += 0.01;
-            dimentia += 0.02;
-            liver_disease += 0.01;
-            kidney_disease += 0.01;
-        } else if self.age < 10 {
-            neonatal += 0.02;
-            diarrheal_disease += 0.01;
-            malnutrition += 0.01;
-        }
-
-        infection += 0.01;
-        diabetes += 0.03;
-        cardiovascular_disease += 0.07; // much people die from cardiovascular disease
+        self.grown_up = true;
 
-        if self.factors.smokes_cigarettes {
-            // cigarette smokers tend to die more from lung cancer, respitory diseases and even
-            // cardiovascular diseases
+        let habits = context.habit_correlations().sample(context, self.sex, rng);
 
-            cancer += 0.02;
-            respiratory_disease += 0.03;
-            cardiovascular_disease += 0.02;
+        for habit in Habit::ALL {
+            if habits[habit.index()] {
+                self.take_up(habit);
+            }
         }
+    }
 
-        if self.factors.smokes_vape {
-            // vapers tend to die more from lung cancer, respitory diseases and even cardiovascular
-            // diseases
+    /// Updates the bean when a policy changes the settings between two years. When fewer people
+    /// should have a habit, each bean with it quits with the chance that brings the share down
+    /// to the new rate, and when more people should, adults without it start. New drinkers take
+    /// up binge drinking with `binge_share`. Diets move by the same amount as the settings.
+    pub fn follow_policy<R: Rng + ?Sized>(
+        &mut self,
+        before: &Settings,
+        after: &Settings,
+        rng: &mut R,
+    ) {
+        let adult = self.age >= ADULT_AGE;
+
+        let binge_share = after.binge_share;
+
+        for habit in Habit::ALL {
+            let (Some(before), Some(after)) = (
+                habit.rate_for(before, self.sex),
+                habit.rate_for(after, self.sex),
+            ) else {
+                continue;
+            };
+
+            let has_habit = self.factors.has(habit);
+
+            if has_habit && after < before {
+                if !rng.gen_bool(after / before) {
+                    self.give_up(habit);
+                }
+            } else if !has_habit
+                && adult
+                && after > before
+                && rng.gen_bool((after - before) / (1.0 - before))
+            {
+                self.take_up(habit);
+
+                // new drinkers binge drink as often as the ones the population started with
+
+                if habit == Habit::Alcoholism && rng.gen_bool(binge_share) {
+                    self.take_up(Habit::BingeDrinker);
+                }
+            }
+        }
 
-            cancer += 0.01;
-            respiratory_disease += 0.02;
-            cardiovascular_disease += 0.01;
+        if !self.factors.alcoholism && self.factors.binge_drinker {
+            self.give_up(Habit::BingeDrinker); // you can't binge drink if you don't drink
         }
 
-        if self.factors.smokes_weed {
-            cancer += 0.005;
+        self.factors.shift_diet(before, after);
+    }
 
-            respiratory_disease += 0.0003;
+    /// Goes through a year of habit transitions: for every habit, the bean might quit it, start
+    /// it for the first time, or relapse into it after quitting, at the chances for its age.
+    pub fn change_habits<R: Rng + ?Sized>(&mut self, transitions: &HabitTransitions, rng: &mut R) {
+        for habit in Habit::ALL {
+            let Some(band) = transitions.band(habit, self.age) else {
+                continue;
+            };
+
+            if self.factors.has(habit) {
+                if rng.gen_bool(band.quit) {
+                    self.give_up(habit);
+                }
+            } else if habit == Habit::BingeDrinker && !self.factors.alcoholism {
+                continue; // only drinkers can start binge drinking
+            } else if self.quit[habit.index()].is_some() {
+                if rng.gen_bool(band.relapse) {
+                    self.take_up(habit);
+                }
+            } else if rng.gen_bool(band.start) {
+                self.take_up(habit);
+            }
         }
 
-        if self.factors.consumes_hard_drugs {
-            cancer += 0.09;
-
-            respiratory_disease += 0.05;
+        if !self.factors.alcoholism && self.factors.binge_drinker {
+            self.give_up(Habit::BingeDrinker);
         }
+    }
 
-        if self.factors.binge_drinker {
-            cancer += 0.02;
+    /// How many years ago the bean gave up a habit, if it has given it up.
+    pub fn years_since_quitting(&self, habit: Habit) -> Option<u32> {
+        self.quit[habit.index()]
+    }
 
-            cardiovascular_disease += 0.03;
-        }
+    fn give_up(&mut self, habit: Habit) {
+        self.factors.set(habit, false);
+        self.quit[habit.index()] = Some(0);
+    }
 
-        if self.factors.alcoholism {
-            cancer += 0.005;
-        }
+    fn take_up(&mut self, habit: Habit) {
+        self.factors.set(habit, true);
+        self.factors
+            .exposures
+            .set_amount(habit, self.amounts.amount(habit));
+        self.quit[habit.index()] = None;
+    }
 
-        if self.factors.sugar.value > 1.0 {
-            // more sugar than recommended
+    pub fn dies<R: Rng + ?Sized>(&self, context: &Settings, rng: &mut R) -> bool {
+        // whether this bean dies or not depending on the factors
+
+        self.cause_of_death(context, rng).is_some()
+    }
 
-            diabetes += 0.02;
+    /// Decides whether this bean dies this year, and if it does, what it dies of. Each cause is
+    /// picked in proportion to how much it contributes to the bean's risk of dying.
+    pub fn cause_of_death<R: Rng + ?Sized>(
+        &self,
+        context: &Settings,
+        rng: &mut R,
+    ) -> Option<CauseOfDeath> {
+        if self.age > context.max_age {
+            return Some(CauseOfDeath::OldAge);
         }
 
-        if self.factors.salt.value > 1.0 {
-            // more salt than recommended
+        let risks = self.risks(context);
+
+        let total: f64 = risks.iter().sum();
 
-            cardiovascular_disease += 0.01;
+        if !rng.gen_bool(total.min(1.0)) {
+            return None; // this bean survives the year
         }
 
-        if self.factors.fat.value > 1.0 {
-            // more fat than recommended
+        let mut pick = rng.gen_range(0.0..total);
 
-            cardiovascular_disease += 0.02;
+        for cause in CauseOfDeath::ALL {
+            let risk = risks[cause.index()];
+
+            if pick < risk {
+                return Some(cause);
+            }
+
+            pick -= risk;
         }
 
-        if self.factors.vitamins.value < 1.0 {
-            // less vitamins than recommended
+        // floating point rounding can leave a tiny bit of the total unassigned
+
+        CauseOfDeath::ALL
+            .into_iter()
+            .rev()
+            .find(|cause| risks[cause.index()] > 0.0)
+    }
 
-            malnutrition += 0.02;
+    /// The chance of this bean dying of each cause this year, indexed by `CauseOfDeath::index`.
+    pub fn risks(&self, context: &Settings) -> [f64; CauseOfDeath::COUNT] {
+        if self.age > context.max_age {
+            let mut risks = [0.0; CauseOfDeath::COUNT];
+            risks[CauseOfDeath::OldAge.index()] = 1.0;
+
+            return risks;
         }
 
-        let death = rng.gen_bool(
-            (cardiovascular_disease
-                + cancer
-                + respiratory_disease
-                + digestive_disease
-                + infection
-                + neonatal
-                + dimentia
-                + diabetes
-                + diarrheal_disease
-                + liver_disease
-                + kidney_disease
-                + malnutrition)
-                / 10.0,
-        );
+        // the baseline for their age, times the relative risks of their habits and diet
+
+        let mut risks = context.hazards().risks(self.age, self.sex, &self.factors);
+
+        // the table's relative risks are for a reference dose, and habits that were given up
+        // still carry some of their risk, however the bean came to give them up
+
+        let half_life = context
+            .habit_transitions()
+            .map_or(DEFAULT_FORMER_RISK_HALF_LIFE, |transitions| {
+                transitions.former_risk_half_life()
+            });
+
+        for habit in Habit::ALL {
+            let remaining = match (self.factors.has(habit), self.quit[habit.index()]) {
+                (true, _) => None,
+                (false, Some(years)) => Some(remaining_risk(half_life, years)),
+                _ => continue,
+            };
 
-        if death {
-            return true; // this bean dies
+            // quitters carry the risk of the amount they used to use
+
+            let mut exposures = self.factors.exposures;
+
+            if remaining.is_some() {
+                exposures.set_amount(habit, self.amounts.amount(habit));
+            }
+
+            let scale = context.doses.scale(habit, &exposures);
+
+            for cause in CauseOfDeath::ALL {
+                let relative_risk = context
+                    .hazards()
+                    .relative_risk(RiskFactor::from(habit), cause);
+
+                let dosed = relative_risk.powf(scale);
+
+                risks[cause.index()] *= match remaining {
+                    None if relative_risk > 0.0 => dosed / relative_risk,
+                    None => 1.0,
+                    Some(remaining) => 1.0 + (dosed - 1.0) * remaining,
+                };
+            }
         }
 
-        false
+        // poorer beans get sick more often, and are less likely to be treated
+
+        context
+            .wealth_effects
+            .apply(self.factors.wealth.value, &mut risks);
+
+        risks
     }
 }
+
+/// Draws an intake from a normal distribution around `average`, cut off at 0.
+fn intake(average: f64, spread: f64, rng: &mut dyn RngCore) -> f64 {
+    TruncatedNormal::new(average, spread, 0.0, f64::INFINITY)
+        .map(|distribution| distribution.sample(rng))
+        .unwrap_or(0.0) // only when the average isn't a number, which validation catches
+}
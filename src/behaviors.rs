@@ -0,0 +1,330 @@
+//! Correlated habits for Beanreadings. People who smoke are far more likely to drink and use
+//! drugs too, so a bean's habits are drawn together instead of with a coin flip each.
+//!
+//! Every habit has a hidden propensity, and the propensities are standard normals correlated by
+//! a matrix (a Gaussian copula). A bean has a habit when its propensity is low enough to hit the
+//! rate in the settings, so every habit is exactly as common as it would be on its own, but
+//! habits that are correlated turn up together more often. The correlations are JSON, giving
+//! each pair once, for example:
+//!
+//! ```json
+//! {
+//!     "smokes_cigarettes": { "alcoholism": 0.4, "smokes_weed": 0.45 },
+//!     "smokes_weed": { "consumes_hard_drugs": 0.5 }
+//! }
+//! ```
+//!
+//! Pairs that are left out aren't correlated. Binge drinking isn't in the matrix, as it is a
+//! kind of drinking: drinkers binge drink with the chance `binge_share` in the settings.
+
+use crate::bean::Sex;
+use crate::distributions::{ContinuousDistribution, NormalDistribution};
+use crate::factors::Habit;
+use crate::types::Settings;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// The habits drawn together, everything but binge drinking.
+pub const CORRELATED_HABITS: [Habit; 5] = [
+    Habit::Alcoholism,
+    Habit::SmokesWeed,
+    Habit::SmokesCigarettes,
+    Habit::SmokesVape,
+    Habit::ConsumesHardDrugs,
+];
+
+const N: usize = CORRELATED_HABITS.len();
+
+/// The correlations used when the settings don't have any. Round numbers that make every pair of
+/// habits turn up together a few times more often than by chance, strongest between cigarettes,
+/// weed and hard drugs.
+const DEFAULT_CORRELATIONS: [(Habit, Habit, f64); 10] = [
+    (Habit::SmokesCigarettes, Habit::Alcoholism, 0.4),
+    (Habit::SmokesCigarettes, Habit::SmokesWeed, 0.45),
+    (Habit::SmokesCigarettes, Habit::SmokesVape, 0.35),
+    (Habit::SmokesCigarettes, Habit::ConsumesHardDrugs, 0.35),
+    (Habit::SmokesWeed, Habit::Alcoholism, 0.4),
+    (Habit::SmokesWeed, Habit::SmokesVape, 0.35),
+    (Habit::SmokesWeed, Habit::ConsumesHardDrugs, 0.5),
+    (Habit::SmokesVape, Habit::Alcoholism, 0.25),
+    (Habit::SmokesVape, Habit::ConsumesHardDrugs, 0.25),
+    (Habit::ConsumesHardDrugs, Habit::Alcoholism, 0.3),
+];
+
+/// A validated correlation matrix for the habits, see the module docs for the format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    try_from = "BTreeMap<String, BTreeMap<String, f64>>",
+    into = "BTreeMap<String, BTreeMap<String, f64>>"
+)]
+pub struct HabitCorrelations {
+    matrix: [[f64; N]; N], // by position in `CORRELATED_HABITS`
+
+    cholesky: [[f64; N]; N], // lower triangular, `cholesky * cholesky^T = matrix`
+}
+
+impl HabitCorrelations {
+    /// Habits that are all independent of each other, like they used to be.
+    pub fn independent() -> HabitCorrelations {
+        let mut identity = [[0.0; N]; N];
+
+        for (i, row) in identity.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+
+        HabitCorrelations {
+            matrix: identity,
+            cholesky: identity,
+        }
+    }
+
+    /// Sets up the correlations between pairs of habits. Fails if a correlation isn't between
+    /// -1 and 1, a pair is given twice with different values, or the correlations contradict
+    /// each other (the matrix isn't positive definite).
+    pub fn new(pairs: &[(Habit, Habit, f64)]) -> Result<HabitCorrelations, String> {
+        let mut matrix = HabitCorrelations::independent().matrix;
+        let mut given = [[false; N]; N];
+
+        for &(first, second, correlation) in pairs {
+            let (Some(i), Some(j)) = (position(first), position(second)) else {
+                return Err(format!(
+                    "{} can't be correlated, it follows from drinking with binge_share",
+                    Habit::BingeDrinker.key()
+                ));
+            };
+
+            if i == j {
+                return Err(format!("{} can't be correlated with itself", first.key()));
+            }
+
+            if !(-1.0..=1.0).contains(&correlation) {
+                return Err(format!(
+                    "the correlation between {} and {} should be between -1 and 1, got {}",
+                    first.key(),
+                    second.key(),
+                    correlation
+                ));
+            }
+
+            if given[i][j] && matrix[i][j] != correlation {
+                return Err(format!(
+                    "the correlation between {} and {} is given twice",
+                    first.key(),
+                    second.key()
+                ));
+            }
+
+            matrix[i][j] = correlation;
+            matrix[j][i] = correlation;
+            given[i][j] = true;
+            given[j][i] = true;
+        }
+
+        let cholesky = cholesky(&matrix).ok_or_else(|| {
+            "the correlations contradict each other, the matrix isn't positive definite".to_string()
+        })?;
+
+        Ok(HabitCorrelations { matrix, cholesky })
+    }
+
+    /// The correlations used when the settings don't have any.
+    pub fn builtin() -> &'static HabitCorrelations {
+        static BUILTIN: OnceLock<HabitCorrelations> = OnceLock::new();
+
+        BUILTIN.get_or_init(|| {
+            HabitCorrelations::new(&DEFAULT_CORRELATIONS)
+                .expect("the default habit correlations should be positive definite")
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<HabitCorrelations, String> {
+        serde_json::from_str(json).map_err(|error| error.to_string())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// The correlation between the propensities for two habits. Binge drinking isn't correlated
+    /// with anything.
+    pub fn correlation(&self, first: Habit, second: Habit) -> f64 {
+        match (position(first), position(second)) {
+            (Some(i), Some(j)) => self.matrix[i][j],
+            _ if first == second => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Draws which habits a bean of a sex has, by `Habit::index`, with each habit as common as in
+    /// the settings for that sex.
+    pub fn sample(
+        &self,
+        settings: &Settings,
+        sex: Sex,
+        rng: &mut dyn RngCore,
+    ) -> [bool; Habit::COUNT] {
+        let normal = NormalDistribution::new(0.0, 1.0);
+
+        let independent: [f64; N] = std::array::from_fn(|_| normal.sample(rng));
+
+        let mut habits = [false; Habit::COUNT];
+
+        for (i, habit) in CORRELATED_HABITS.into_iter().enumerate() {
+            let propensity: f64 = (0..=i).map(|j| self.cholesky[i][j] * independent[j]).sum();
+
+            // the propensity is a standard normal, so its CDF is uniform and this happens with
+            // exactly the chance in the settings
+
+            habits[habit.index()] =
+                normal.cdf(propensity) < habit.rate_for(settings, sex).unwrap_or(0.0);
+        }
+
+        habits[Habit::BingeDrinker.index()] =
+            habits[Habit::Alcoholism.index()] && rng.gen_bool(settings.binge_share);
+
+        habits
+    }
+}
+
+fn position(habit: Habit) -> Option<usize> {
+    CORRELATED_HABITS.iter().position(|&other| other == habit)
+}
+
+/// The Cholesky decomposition of a symmetric matrix, or `None` if it isn't positive definite.
+fn cholesky(matrix: &[[f64; N]; N]) -> Option<[[f64; N]; N]> {
+    let mut lower = [[0.0; N]; N];
+
+    for i in 0..N {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| lower[i][k] * lower[j][k]).sum();
+
+            if i == j {
+                let diagonal = matrix[i][i] - sum;
+
+                if diagonal <= 1e-12 {
+                    return None;
+                }
+
+                lower[i][j] = diagonal.sqrt();
+            } else {
+                lower[i][j] = (matrix[i][j] - sum) / lower[j][j];
+            }
+        }
+    }
+
+    Some(lower)
+}
+
+impl TryFrom<BTreeMap<String, BTreeMap<String, f64>>> for HabitCorrelations {
+    type Error = String;
+
+    fn try_from(file: BTreeMap<String, BTreeMap<String, f64>>) -> Result<Self, Self::Error> {
+        let habit =
+            |key: &str| Habit::from_key(key).ok_or_else(|| format!("unknown habit {}", key));
+
+        let mut pairs = Vec::new();
+
+        for (first, others) in &file {
+            for (second, &correlation) in others {
+                pairs.push((habit(first)?, habit(second)?, correlation));
+            }
+        }
+
+        HabitCorrelations::new(&pairs)
+    }
+}
+
+impl From<HabitCorrelations> for BTreeMap<String, BTreeMap<String, f64>> {
+    fn from(correlations: HabitCorrelations) -> Self {
+        let mut file: BTreeMap<String, BTreeMap<String, f64>> = BTreeMap::new();
+
+        for (i, first) in CORRELATED_HABITS.into_iter().enumerate() {
+            for (j, second) in CORRELATED_HABITS.into_iter().enumerate().skip(i + 1) {
+                if correlations.matrix[i][j] != 0.0 {
+                    file.entry(first.key().to_string())
+                        .or_default()
+                        .insert(second.key().to_string(), correlations.matrix[i][j]);
+                }
+            }
+        }
+
+        file
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rng::seeded;
+
+    #[test]
+    fn test_correlated_habits() {
+        let mut settings = Settings::new();
+        settings.set_smokers(0.3);
+        settings.set_drinkers(0.4);
+
+        let mut rng = seeded(22);
+
+        let count = |correlations: &HabitCorrelations, rng: &mut dyn RngCore| {
+            let mut counts = [0.0; 4]; // smokers, drinkers, both, binge drinkers
+
+            for i in 0..40000 {
+                let habits = correlations.sample(&settings, Sex::ALL[i % 2], rng);
+                let smokes = habits[Habit::SmokesCigarettes.index()];
+                let drinks = habits[Habit::Alcoholism.index()];
+
+                assert!(drinks || !habits[Habit::BingeDrinker.index()]);
+
+                for (total, has) in counts.iter_mut().zip([
+                    smokes,
+                    drinks,
+                    smokes && drinks,
+                    habits[Habit::BingeDrinker.index()],
+                ]) {
+                    *total += has as u8 as f64 / 40000.0;
+                }
+            }
+
+            counts
+        };
+
+        // the rates in the settings are hit either way, but smokers drink more often with the
+        // default correlations
+
+        let [smokers, drinkers, both, binge] = count(HabitCorrelations::builtin(), &mut rng);
+
+        assert!((smokers - 0.3).abs() < 0.01);
+        assert!((drinkers - 0.4).abs() < 0.01);
+        assert!(both > 0.3 * 0.4 + 0.05);
+        assert!((binge / drinkers - 1.0 / 3.0).abs() < 0.02);
+
+        let [smokers, drinkers, both, _] = count(&HabitCorrelations::independent(), &mut rng);
+
+        assert!((both - smokers * drinkers).abs() < 0.01);
+
+        // JSON goes both ways, and impossible matrices are turned away
+
+        let json = HabitCorrelations::builtin().to_json();
+
+        assert_eq!(
+            &HabitCorrelations::from_json(&json).unwrap(),
+            HabitCorrelations::builtin()
+        );
+        assert_eq!(
+            HabitCorrelations::builtin().correlation(Habit::SmokesWeed, Habit::ConsumesHardDrugs),
+            0.5
+        );
+        assert!(HabitCorrelations::from_json(
+            r#"{
+                "alcoholism": { "smokes_weed": 0.9, "smokes_vape": 0.9 },
+                "smokes_weed": { "smokes_vape": -0.9 }
+            }"#
+        )
+        .is_err());
+        assert!(
+            HabitCorrelations::from_json(r#"{ "binge_drinker": { "alcoholism": 0.5 } }"#).is_err()
+        );
+    }
+}
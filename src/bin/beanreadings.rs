@@ -0,0 +1,511 @@
+//! Command line runner for Beanreadings, for running scenarios in batches without a browser.
+//!
+//! ```text
+//! beanreadings [options] <scenario.json>...
+//! ```
+//!
+//! Every scenario is run with the agent engine, or as an ensemble with `--ensemble`, and the
+//! results are written to stdout or to one file per scenario in `--output`. JSON is always a list
+//! of scenarios, even when there is only one.
+
+use simulation::{EnsembleResult, Scenario, Settings, Simulation, SimulationError};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: beanreadings [options] <scenario.json>...
+
+Runs every scenario file and writes the results as CSV or JSON.
+
+Options:
+    --ensemble <N>           run N replicates and summarise them instead of a single run
+    --percentiles <P,P,...>  percentile bands for ensembles, from 0 to 100 (default 5,50,95)
+    --format <csv|json>      output format (default csv)
+    --output <DIR>           write one file per scenario to DIR instead of stdout
+    --set <KEY=VALUE>        override a setting in every scenario, e.g. --set smokers=0.1
+                             (nested settings use dots, e.g. wealth_effects.infection=1.5)
+    -h, --help               show this message";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Debug)]
+struct Options {
+    scenarios: Vec<PathBuf>,
+    ensemble: Option<u32>,
+    percentiles: Vec<f64>,
+    format: Format,
+    output: Option<PathBuf>,
+    overrides: Vec<(String, String)>,
+}
+
+/// The result of running one scenario.
+enum Outcome {
+    Single(Vec<(String, Vec<f64>)>),
+    Ensemble(EnsembleResult),
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        let _ = write_stdout(&format!("{}\n", USAGE));
+
+        return ExitCode::SUCCESS;
+    }
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        scenarios: Vec::new(),
+        ensemble: None,
+        percentiles: vec![5.0, 50.0, 95.0],
+        format: Format::Csv,
+        output: None,
+        overrides: Vec::new(),
+    };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} needs a value", arg))
+                .cloned()
+        };
+
+        match arg.as_str() {
+            "--ensemble" => {
+                let replicates = value()?;
+                let replicates = replicates
+                    .parse()
+                    .ok()
+                    .filter(|&replicates| replicates > 0)
+                    .ok_or_else(|| {
+                        format!("--ensemble should be a positive number, got {}", replicates)
+                    })?;
+
+                options.ensemble = Some(replicates);
+            }
+            "--percentiles" => {
+                options.percentiles = value()?
+                    .split(',')
+                    .map(|percentile| {
+                        percentile
+                            .trim()
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|percentile| (0.0..=100.0).contains(percentile))
+                            .ok_or_else(|| format!("invalid percentile {}", percentile))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "--format" => {
+                options.format = match value()?.as_str() {
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format {}, use csv or json", other)),
+                };
+            }
+            "--output" => options.output = Some(PathBuf::from(value()?)),
+            "--set" => {
+                let setting = value()?;
+                let (key, value) = setting
+                    .split_once('=')
+                    .ok_or_else(|| format!("--set should look like key=value, got {}", setting))?;
+
+                options
+                    .overrides
+                    .push((key.trim().to_string(), value.trim().to_string()));
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            path => options.scenarios.push(PathBuf::from(path)),
+        }
+    }
+
+    if options.scenarios.is_empty() {
+        return Err("no scenario files given".to_string());
+    }
+
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    if let Some(output) = &options.output {
+        check_names(&options.scenarios)?;
+
+        std::fs::create_dir_all(output)
+            .map_err(|error| format!("can't create {}: {}", output.display(), error))?;
+    }
+
+    let mut outcomes = Vec::new();
+
+    for path in &options.scenarios {
+        let name = scenario_name(path);
+
+        let outcome =
+            run_scenario(path, options).map_err(|error| format!("{}: {}", name, error))?;
+
+        match &options.output {
+            Some(output) => {
+                let extension = match options.format {
+                    Format::Csv => "csv",
+                    Format::Json => "json",
+                };
+
+                let file = output.join(format!("{}.{}", name, extension));
+                let contents = render(options.format, &[(name, outcome)]);
+
+                std::fs::write(&file, contents)
+                    .map_err(|error| format!("can't write {}: {}", file.display(), error))?;
+
+                eprintln!("wrote {}", file.display());
+            }
+            None => outcomes.push((name, outcome)),
+        }
+    }
+
+    if options.output.is_none() {
+        write_stdout(&render(options.format, &outcomes))?;
+    }
+
+    Ok(())
+}
+
+/// Writes to stdout, stopping quietly if whatever reads it has gone away, like `head` does once
+/// it has enough lines.
+fn write_stdout(text: &str) -> Result<(), String> {
+    let mut stdout = io::stdout().lock();
+
+    match stdout
+        .write_all(text.as_bytes())
+        .and_then(|()| stdout.flush())
+    {
+        Err(error) if error.kind() != io::ErrorKind::BrokenPipe => {
+            Err(format!("can't write the results: {}", error))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Files in `--output` are named after their scenarios, so two scenarios with the same name in
+/// different folders would write over each other.
+fn check_names(scenarios: &[PathBuf]) -> Result<(), String> {
+    let mut seen: HashMap<String, &PathBuf> = HashMap::new();
+
+    for path in scenarios {
+        if let Some(first) = seen.insert(scenario_name(path), path) {
+            return Err(format!(
+                "{} and {} have the same name, so their results would go to the same file",
+                first.display(),
+                path.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn scenario_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn run_scenario(path: &Path, options: &Options) -> Result<Outcome, String> {
+    let json = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+
+    let scenario = Scenario::from_json(&json)?;
+
+    // the scenario's seed has already been copied into its settings, so overrides can change it
+
+    let settings = apply_overrides(&scenario.settings, &options.overrides)?;
+    let simulation = Simulation::new(settings);
+
+    let describe = |error: SimulationError| error.to_string();
+
+    Ok(match options.ensemble {
+        Some(replicates) => Outcome::Ensemble(
+            simulation
+                .try_ensemble(replicates, options.percentiles.clone())
+                .map_err(describe)?,
+        ),
+        None => Outcome::Single(simulation.try_long().map_err(describe)?.series()),
+    })
+}
+
+/// Changes settings by name, going through JSON so that every setting can be overridden
+/// without listing them all here. Values are read as JSON, so numbers, booleans and lists all
+/// work.
+fn apply_overrides(
+    settings: &Settings,
+    overrides: &[(String, String)],
+) -> Result<Settings, String> {
+    if overrides.is_empty() {
+        return Ok(settings.clone());
+    }
+
+    let mut json: serde_json::Value =
+        serde_json::from_str(&settings.to_json()).map_err(|error| error.to_string())?;
+
+    for (key, value) in overrides {
+        let value = serde_json::from_str(value)
+            .unwrap_or_else(|_| serde_json::Value::String(value.clone()));
+
+        let mut target = &mut json;
+        let mut parts = key.split('.').enumerate().peekable();
+
+        while let Some((depth, part)) = parts.next() {
+            let object = target
+                .as_object_mut()
+                .ok_or_else(|| format!("can't set {}, it isn't a group of settings", key))?;
+
+            // unknown settings are caught when the JSON is read back in, but groups of settings
+            // take anything, so those have to have the setting already
+
+            if parts.peek().is_none() {
+                if depth > 0 && !object.contains_key(part) {
+                    return Err(format!("unknown setting {}", key));
+                }
+
+                object.insert(part.to_string(), value.clone());
+            }
+
+            target = object
+                .get_mut(part)
+                .ok_or_else(|| format!("unknown setting {}", key))?;
+        }
+    }
+
+    Settings::from_json(&json.to_string())
+}
+
+fn render(format: Format, outcomes: &[(String, Outcome)]) -> String {
+    match format {
+        Format::Csv => render_csv(outcomes),
+        Format::Json => render_json(outcomes),
+    }
+}
+
+/// Single runs get a column for every series and a row for every year. Ensembles get a row for
+/// every series and year, with the summary statistics as columns.
+fn render_csv(outcomes: &[(String, Outcome)]) -> String {
+    let mut csv = String::new();
+    let mut header_written = [false, false];
+
+    for (name, outcome) in outcomes {
+        match outcome {
+            Outcome::Single(series) => {
+                if !header_written[0] {
+                    let names: Vec<&str> = series.iter().map(|(name, _)| name.as_str()).collect();
+                    let _ = writeln!(csv, "scenario,year,{}", names.join(","));
+                    header_written[0] = true;
+                }
+
+                let years = series.first().map_or(0, |(_, values)| values.len());
+
+                for year in 0..years {
+                    let values: Vec<String> = series
+                        .iter()
+                        .map(|(_, values)| values[year].to_string())
+                        .collect();
+
+                    let _ = writeln!(csv, "{},{},{}", name, year + 1, values.join(","));
+                }
+            }
+            Outcome::Ensemble(ensemble) => {
+                if !header_written[1] {
+                    let bands: Vec<String> = ensemble
+                        .get_percentiles()
+                        .iter()
+                        .map(|percentile| format!(",p{}", percentile))
+                        .collect();
+
+                    let _ = writeln!(
+                        csv,
+                        "scenario,series,year,mean,median,std_dev{}",
+                        bands.concat()
+                    );
+                    header_written[1] = true;
+                }
+
+                for summary in ensemble.all_series() {
+                    for year in 0..summary.mean.len() {
+                        let bands: Vec<String> = summary
+                            .bands
+                            .iter()
+                            .map(|band| format!(",{}", band[year]))
+                            .collect();
+
+                        let _ = writeln!(
+                            csv,
+                            "{},{},{},{},{},{}{}",
+                            name,
+                            summary.name,
+                            year + 1,
+                            summary.mean[year],
+                            summary.median[year],
+                            summary.std_dev[year],
+                            bands.concat()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    csv
+}
+
+fn render_json(outcomes: &[(String, Outcome)]) -> String {
+    let outcomes: Vec<serde_json::Value> = outcomes
+        .iter()
+        .map(|(name, outcome)| match outcome {
+            Outcome::Single(series) => serde_json::json!({
+                "scenario": name,
+                "series": series
+                    .iter()
+                    .map(|(name, values)| serde_json::json!({ "name": name, "values": values }))
+                    .collect::<Vec<_>>(),
+            }),
+            Outcome::Ensemble(ensemble) => serde_json::json!({
+                "scenario": name,
+                "ensemble": ensemble,
+            }),
+        })
+        .collect();
+
+    // always a list, even of one scenario, so scripts can read every file the same way
+
+    serde_json::to_string_pretty(&outcomes).unwrap_or_default() + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&args(
+            "--ensemble 8 --format json --set wealth_effects.infection=1.5 a.json b.json",
+        ))
+        .unwrap();
+
+        assert_eq!(options.ensemble, Some(8));
+        assert_eq!(options.format, Format::Json);
+        assert_eq!(
+            options.overrides,
+            vec![("wealth_effects.infection".to_string(), "1.5".to_string())]
+        );
+        assert_eq!(options.scenarios.len(), 2);
+
+        assert!(parse_args(&args("--ensemble 0 a.json")).is_err());
+        assert!(parse_args(&args("--set smokers a.json")).is_err());
+        assert!(parse_args(&args("--format xml a.json")).is_err());
+        assert!(parse_args(&args("--ensemble 4")).is_err());
+    }
+
+    #[test]
+    fn test_apply_overrides() {
+        let overrides = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+
+        let settings = Settings::new();
+
+        let changed = apply_overrides(
+            &settings,
+            &overrides(&[("smokers", "0.1"), ("wealth_effects.infection", "1.5")]),
+        )
+        .unwrap();
+
+        assert_eq!(changed.smokers, 0.1);
+        assert_eq!(changed.wealth_effects.infection, 1.5);
+        assert_eq!(changed.drinkers, settings.drinkers);
+
+        // unknown settings are turned away, at the top and inside groups
+
+        assert!(apply_overrides(&settings, &overrides(&[("knitting", "1")])).is_err());
+        assert!(apply_overrides(&settings, &overrides(&[("wealth_effects.nope", "1")])).is_err());
+        assert!(apply_overrides(&settings, &overrides(&[("smokers.daily", "1")])).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let outcomes = vec![
+            (
+                "base".to_string(),
+                Outcome::Single(vec![
+                    ("population".to_string(), vec![10.0, 9.0]),
+                    ("deaths".to_string(), vec![1.0, 2.0]),
+                ]),
+            ),
+            (
+                "policy".to_string(),
+                Outcome::Single(vec![
+                    ("population".to_string(), vec![10.0, 11.0]),
+                    ("deaths".to_string(), vec![0.0, 0.5]),
+                ]),
+            ),
+        ];
+
+        // one header, then a row for every scenario and year
+
+        assert_eq!(
+            render_csv(&outcomes),
+            "scenario,year,population,deaths\n\
+             base,1,10,1\n\
+             base,2,9,2\n\
+             policy,1,10,0\n\
+             policy,2,11,0.5\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&render_json(&outcomes)).unwrap();
+
+        assert_eq!(json[1]["scenario"], "policy");
+        assert_eq!(json[1]["series"][1]["values"][1], 0.5);
+
+        let json: serde_json::Value = serde_json::from_str(&render_json(&outcomes[..1])).unwrap();
+
+        assert_eq!(json[0]["series"][0]["name"], "population");
+    }
+
+    #[test]
+    fn test_check_names() {
+        let paths = |paths: &[&str]| -> Vec<PathBuf> { paths.iter().map(PathBuf::from).collect() };
+
+        assert!(check_names(&paths(&["a/x.json", "a/y.json", "b/z.json"])).is_ok());
+        assert!(check_names(&paths(&["a/x.json", "b/x.json"])).is_err());
+    }
+}
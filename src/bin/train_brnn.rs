@@ -0,0 +1,231 @@
+//! Trainer for the BRNN surrogate: runs the simulation over sampled settings, trains a network on
+//! the results and reports how it does on held out runs, next to the bundled network.
+//!
+//! ```text
+//! train_brnn [options]
+//! ```
+//!
+//! The defaults are the ones the bundled network was made with: `cargo run --release --bin
+//! train_brnn` runs 8000 simulations from seed 0, trains 4 hidden layers of 48 neurons for 1500
+//! epochs and writes `trained_brnn.json`, which is then copied over `brnn.json`. Like any run, it
+//! only gives the same network on the same platform (see `rng.rs`).
+
+use simulation::{
+    evaluate, train, AccuracyReport, Brnn, Dataset, ErrorTable, SampleRanges, Settings,
+    TrainingOptions,
+};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: train_brnn [options]
+
+Generates training data by running the simulation, trains a network on it and prints a table of
+errors on held out runs.
+
+Options:
+    --samples <N>            number of simulation runs to generate (default 8000)
+    --test-fraction <F>      share of the runs held out for testing (default 0.2)
+    --seed <N>               seed for sampling, the runs and training (default 0)
+    --hidden <N,N,...>       sizes of the hidden layers (default 48,48,48,48)
+    --epochs <N>             passes over the training data (default 1500)
+    --learning-rate <F>      Adam learning rate (default 0.003)
+    --dataset <FILE>         also write the generated runs to FILE as CSV
+    --from-dataset <FILE>    train on runs from a CSV file instead of generating them
+    --output <FILE>          where to write the trained network as JSON (default
+                             trained_brnn.json, copy it over brnn.json to bundle it)
+    --report <FILE>          also write the error table to FILE as Markdown
+    --accuracy <FILE>        also write an accuracy report of the trained network on the held
+                             out runs to FILE as JSON, for the UI to warn with
+    -h, --help               show this message";
+
+/// Regions where the trained network is off by more than this on average are reported as
+/// unreliable.
+const ACCURACY_TOLERANCE: f64 = 0.2;
+
+#[derive(Debug)]
+struct Options {
+    samples: usize,
+    test_fraction: f64,
+    seed: u64,
+    training: TrainingOptions,
+    dataset: Option<PathBuf>,
+    from_dataset: Option<PathBuf>,
+    output: PathBuf,
+    report: Option<PathBuf>,
+    accuracy: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+
+        return ExitCode::SUCCESS;
+    }
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        samples: 8000,
+        test_fraction: 0.2,
+        seed: 0,
+        training: TrainingOptions {
+            hidden: vec![48; 4],
+            epochs: 1500,
+            ..TrainingOptions::default()
+        },
+        dataset: None,
+        from_dataset: None,
+        output: PathBuf::from("trained_brnn.json"), // not brnn.json, which is built into the crate
+        report: None,
+        accuracy: None,
+    };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} needs a value", arg))
+                .cloned()
+        };
+
+        let number = |value: String| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite() && *number >= 0.0)
+                .ok_or_else(|| format!("{} should be a number, got {}", arg, value))
+        };
+
+        match arg.as_str() {
+            "--samples" => options.samples = number(value()?)? as usize,
+            "--test-fraction" => {
+                options.test_fraction = number(value()?)?;
+
+                if options.test_fraction >= 1.0 {
+                    return Err("--test-fraction should be below 1".to_string());
+                }
+            }
+            "--seed" => options.seed = number(value()?)? as u64,
+            "--hidden" => {
+                options.training.hidden = value()?
+                    .split(',')
+                    .map(|size| {
+                        size.trim()
+                            .parse()
+                            .ok()
+                            .filter(|&size| size > 0)
+                            .ok_or_else(|| format!("invalid layer size {}", size))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "--epochs" => options.training.epochs = number(value()?)? as u32,
+            "--learning-rate" => options.training.learning_rate = number(value()?)?,
+            "--dataset" => options.dataset = Some(PathBuf::from(value()?)),
+            "--from-dataset" => options.from_dataset = Some(PathBuf::from(value()?)),
+            "--output" => options.output = PathBuf::from(value()?),
+            "--report" => options.report = Some(PathBuf::from(value()?)),
+            "--accuracy" => options.accuracy = Some(PathBuf::from(value()?)),
+            other => return Err(format!("unknown option {}", other)),
+        }
+    }
+
+    options.training.seed = options.seed;
+
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let data = match &options.from_dataset {
+        Some(path) => {
+            let csv = std::fs::read_to_string(path)
+                .map_err(|error| format!("can't read {}: {}", path.display(), error))?;
+
+            Dataset::from_csv(&csv)?
+        }
+        None => {
+            let mut base = Settings::new();
+            base.set_seed(options.seed);
+
+            eprintln!("running {} simulations", options.samples);
+
+            Dataset::generate(options.samples, &SampleRanges::default(), &base)
+        }
+    };
+
+    if let Some(path) = &options.dataset {
+        write(path, &data.to_csv())?;
+    }
+
+    let (train_set, test_set) = data.split(options.test_fraction, options.seed);
+
+    eprintln!(
+        "training on {} runs, testing on {}",
+        train_set.len(),
+        test_set.len()
+    );
+
+    let network = train(&train_set, &options.training)?;
+
+    write(&options.output, &network.to_json())?;
+
+    let table = ErrorTable {
+        rows: vec![
+            evaluate("bundled", Brnn::bundled(), &test_set),
+            evaluate("trained", &network, &test_set),
+        ],
+    }
+    .to_markdown();
+
+    print!("{}", table);
+
+    if let Some(path) = &options.report {
+        write(path, &table)?;
+    }
+
+    if let Some(path) = &options.accuracy {
+        let results: Vec<(f64, f64)> = test_set
+            .features
+            .iter()
+            .zip(&test_set.labels)
+            .map(|(features, &label)| (network.forward(features.to_vec())[0] as f64, label as f64))
+            .collect();
+
+        let report = AccuracyReport::from_results(&test_set.features, &results, ACCURACY_TOLERANCE)
+            .map_err(|error| error.to_string())?;
+
+        write(path, &report.to_json())?;
+    }
+
+    Ok(())
+}
+
+fn write(path: &PathBuf, contents: &str) -> Result<(), String> {
+    std::fs::write(path, contents)
+        .map_err(|error| format!("can't write {}: {}", path.display(), error))?;
+
+    eprintln!("wrote {}", path.display());
+
+    Ok(())
+}
@@ -0,0 +1,569 @@
+//! The Beanreadings Neural Network (BRNN), a small surrogate model that predicts the outcome of a
+//! simulation without running it. It is a plain multilayer perceptron: 16 inputs, hidden layers
+//! with ReLU activations, and 1 output, the population at the end of the run.
+//!
+//! The bundled network (`brnn.json`, built into the binary) was made by running `train_brnn` with
+//! its default options, which use seed 0: 8000 runs of the agent engine and 4 hidden layers of 48
+//! neurons, trained for 1500 epochs. It is off by about 4.4% on average on the runs it held out,
+//! for populations of 100 to 5000 and up to 50 years.
+//!
+//! Networks can also be read from ONNX, so ones trained in PyTorch work too. Instead of depending
+//! on an ONNX runtime, we read the weights straight out of the file with a tiny protobuf reader,
+//! and run the forward pass ourselves, so it works the same on the web and natively. Only the
+//! operators the network uses (`Gemm` and `Relu`) are supported. `brnn.onnx` is the first network,
+//! trained in PyTorch. Nobody recorded which inputs it was trained on, and it predicts
+//! populations about ten times too big, so it is only kept to test the ONNX reader.
+//!
+//! ONNX files don't say what their inputs mean, so this is the order we feed the network, and the
+//! order any network for Beanreadings has to be trained with. The 16 inputs are the numeric
+//! settings, without any scaling:
+//!
+//! | #  | setting        | #  | setting              |
+//! |----|----------------|----|----------------------|
+//! | 0  | `years`        | 8  | `sugar`              |
+//! | 1  | `population`   | 9  | `salt`               |
+//! | 2  | `max_age`      | 10 | `fat`                |
+//! | 3  | `smokers`      | 11 | `vitamins`           |
+//! | 4  | `weed_smokers` | 12 | `wealth_factor`      |
+//! | 5  | `vapers`       | 13 | `fertility_rate`     |
+//! | 6  | `drinkers`     | 14 | `sex_ratio_at_birth` |
+//! | 7  | `hard_drugger` | 15 | `twin_rate`          |
+//!
+//! `surrogate.rs` can train a new network on simulation runs, saved as JSON and loaded with
+//! `Brnn::from_json`.
+//!
+//! Policies, custom hazard tables and the other non-numeric settings are not inputs, so the
+//! prediction is only a quick estimate for the settings at the start of the run.
+
+use crate::types::{invalid_parameters, Settings, Simulation};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// How many inputs the network takes, see the module docs for what they are.
+pub const FEATURES: usize = 16;
+
+/// The names of the inputs, the same as the names of the settings.
+pub const FEATURE_NAMES: [&str; FEATURES] = [
+    "years",
+    "population",
+    "max_age",
+    "smokers",
+    "weed_smokers",
+    "vapers",
+    "drinkers",
+    "hard_drugger",
+    "sugar",
+    "salt",
+    "fat",
+    "vitamins",
+    "wealth_factor",
+    "fertility_rate",
+    "sex_ratio_at_birth",
+    "twin_rate",
+];
+
+const BUNDLED_MODEL: &str = include_str!("../brnn.json");
+
+/// The inputs of the network for some settings, in the order listed in the module docs.
+pub fn features(settings: &Settings) -> [f32; FEATURES] {
+    [
+        settings.years as f32,
+        settings.population as f32,
+        settings.max_age as f32,
+        settings.smokers as f32,
+        settings.weed_smokers as f32,
+        settings.vapers as f32,
+        settings.drinkers as f32,
+        settings.hard_drugger as f32,
+        settings.sugar as f32,
+        settings.salt as f32,
+        settings.fat as f32,
+        settings.vitamins as f32,
+        settings.wealth_factor as f32,
+        settings.fertility_rate as f32,
+        settings.sex_ratio_at_birth as f32,
+        settings.twin_rate as f32,
+    ]
+}
+
+/// The opposite of `features`, sets the settings from the inputs of the network. Whole number
+/// settings are rounded.
+pub fn apply_features(settings: &mut Settings, values: &[f64; FEATURES]) {
+    settings.years = values[0].round().max(0.0) as u32;
+    settings.population = values[1].round().max(0.0) as u32;
+    settings.max_age = values[2].round().max(0.0) as u32;
+    settings.smokers = values[3];
+    settings.weed_smokers = values[4];
+    settings.vapers = values[5];
+    settings.drinkers = values[6];
+    settings.hard_drugger = values[7];
+    settings.sugar = values[8];
+    settings.salt = values[9];
+    settings.fat = values[10];
+    settings.vitamins = values[11];
+    settings.wealth_factor = values[12];
+    settings.fertility_rate = values[13];
+    settings.sex_ratio_at_birth = values[14];
+    settings.twin_rate = values[15];
+}
+
+/// A fully connected layer, `outputs = weights * inputs + bias`, optionally followed by a ReLU.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    pub inputs: usize,
+    pub outputs: usize,
+    pub weights: Vec<f32>, // `outputs` rows of `inputs` weights each
+    pub bias: Vec<f32>,
+    pub relu: bool,
+}
+
+impl Layer {
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        self.weights
+            .chunks(self.inputs)
+            .zip(&self.bias)
+            .map(|(row, bias)| {
+                let sum = row.iter().zip(input).map(|(w, x)| w * x).sum::<f32>() + bias;
+
+                if self.relu {
+                    sum.max(0.0)
+                } else {
+                    sum
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Brnn {
+    layers: Vec<Layer>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Brnn {
+    /// Loads a network from the bytes of an ONNX file.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn from_onnx(bytes: &[u8]) -> Result<Brnn, String> {
+        onnx::read(bytes)
+    }
+
+    /// Loads a network from the JSON written by `to_json`, like the ones the trainer exports.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn from_json(json: &str) -> Result<Brnn, String> {
+        let network: Brnn = serde_json::from_str(json).map_err(invalid_parameters)?;
+
+        Brnn::new(network.layers).map_err(invalid_parameters)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Runs the network on its raw inputs.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn forward(&self, input: Vec<f32>) -> Vec<f32> {
+        self.layers
+            .iter()
+            .fold(input, |values, layer| layer.forward(&values))
+    }
+
+    /// Predicts the population at the end of a simulation with these settings.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn predict(&self, settings: &Settings) -> f64 {
+        let output = self.forward(features(settings).to_vec());
+
+        output.first().copied().unwrap_or(0.0) as f64
+    }
+}
+
+impl Brnn {
+    pub fn new(layers: Vec<Layer>) -> Result<Brnn, String> {
+        for (index, layer) in layers.iter().enumerate() {
+            if layer.weights.len() != layer.inputs * layer.outputs
+                || layer.bias.len() != layer.outputs
+            {
+                return Err(format!("layer {} has the wrong number of weights", index));
+            }
+
+            if index > 0 && layers[index - 1].outputs != layer.inputs {
+                return Err(format!(
+                    "layer {} doesn't fit onto the layer before it",
+                    index
+                ));
+            }
+        }
+
+        match (layers.first(), layers.last()) {
+            (Some(first), Some(last)) if first.inputs == FEATURES && last.outputs == 1 => {
+                Ok(Brnn { layers })
+            }
+            _ => Err(format!(
+                "the network should take {} inputs and give 1 output",
+                FEATURES
+            )),
+        }
+    }
+
+    /// The network that comes with Beanreadings.
+    pub fn bundled() -> &'static Brnn {
+        static MODEL: OnceLock<Brnn> = OnceLock::new();
+
+        MODEL.get_or_init(|| Brnn::from_json(BUNDLED_MODEL).expect("brnn.json is a valid network"))
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Simulation {
+    /// Predicts the population at the end of the simulation with the bundled network, without
+    /// running it. Much faster than `long`, but only an estimate.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn predict(&self) -> f64 {
+        Brnn::bundled().predict(&self.settings)
+    }
+
+    /// Predicts the population at the end of the simulation with another network, like one made
+    /// by the trainer in `surrogate.rs`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn predict_with(&self, network: &Brnn) -> f64 {
+        network.predict(&self.settings)
+    }
+}
+
+/// Just enough of ONNX to read a chain of `Gemm` and `Relu` nodes. ONNX files are protobuf
+/// messages, the field numbers come from `onnx.proto`.
+mod onnx {
+    use super::{Brnn, Layer};
+    use std::collections::HashMap;
+
+    const MODEL_GRAPH: u64 = 7;
+
+    const GRAPH_NODE: u64 = 1;
+    const GRAPH_INITIALIZER: u64 = 5;
+
+    const NODE_INPUT: u64 = 1;
+    const NODE_OP_TYPE: u64 = 4;
+    const NODE_ATTRIBUTE: u64 = 5;
+
+    const ATTRIBUTE_NAME: u64 = 1;
+    const ATTRIBUTE_FLOAT: u64 = 2;
+    const ATTRIBUTE_INT: u64 = 3;
+
+    const TENSOR_DIMS: u64 = 1;
+    const TENSOR_DATA_TYPE: u64 = 2;
+    const TENSOR_FLOAT_DATA: u64 = 4;
+    const TENSOR_NAME: u64 = 8;
+    const TENSOR_RAW_DATA: u64 = 9;
+
+    const FLOAT: u64 = 1;
+
+    /// A field of a protobuf message. Varints and fixed width numbers are both read into `Number`.
+    enum Value<'a> {
+        Number(u64),
+        Bytes(&'a [u8]),
+    }
+
+    fn varint(bytes: &[u8], position: &mut usize) -> Result<u64, String> {
+        let mut value = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = *bytes
+                .get(*position)
+                .ok_or("the file ends in the middle of a number")?;
+            *position += 1;
+
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+
+        Err("a number in the file is too long".to_string())
+    }
+
+    fn take<'a>(bytes: &'a [u8], position: &mut usize, length: usize) -> Result<&'a [u8], String> {
+        let end = position
+            .checked_add(length)
+            .filter(|&end| end <= bytes.len())
+            .ok_or("the file ends in the middle of a field")?;
+
+        let taken = &bytes[*position..end];
+        *position = end;
+
+        Ok(taken)
+    }
+
+    fn fields(bytes: &[u8]) -> Result<Vec<(u64, Value<'_>)>, String> {
+        let mut fields = Vec::new();
+        let mut position = 0;
+
+        while position < bytes.len() {
+            let key = varint(bytes, &mut position)?;
+
+            let value = match key & 7 {
+                0 => Value::Number(varint(bytes, &mut position)?),
+                1 => {
+                    let data = take(bytes, &mut position, 8)?;
+                    Value::Number(u64::from_le_bytes(data.try_into().expect("8 bytes")))
+                }
+                2 => {
+                    let length = varint(bytes, &mut position)? as usize;
+                    Value::Bytes(take(bytes, &mut position, length)?)
+                }
+                5 => {
+                    let data = take(bytes, &mut position, 4)?;
+                    Value::Number(u32::from_le_bytes(data.try_into().expect("4 bytes")) as u64)
+                }
+                wire => return Err(format!("unsupported protobuf wire type {}", wire)),
+            };
+
+            fields.push((key >> 3, value));
+        }
+
+        Ok(fields)
+    }
+
+    fn bytes_of<'a, 'b>(
+        fields: &'b [(u64, Value<'a>)],
+        number: u64,
+    ) -> impl Iterator<Item = &'a [u8]> + 'b {
+        fields.iter().filter_map(move |(field, value)| match value {
+            Value::Bytes(bytes) if *field == number => Some(*bytes),
+            _ => None,
+        })
+    }
+
+    fn number_of(fields: &[(u64, Value<'_>)], number: u64) -> Option<u64> {
+        fields.iter().find_map(|(field, value)| match value {
+            Value::Number(value) if *field == number => Some(*value),
+            _ => None,
+        })
+    }
+
+    fn string_of(fields: &[(u64, Value<'_>)], number: u64) -> String {
+        bytes_of(fields, number)
+            .next()
+            .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+            .unwrap_or_default()
+    }
+
+    struct Tensor {
+        dims: Vec<usize>,
+        data: Vec<f32>,
+    }
+
+    fn tensor(bytes: &[u8]) -> Result<(String, Tensor), String> {
+        let fields = fields(bytes)?;
+        let name = string_of(&fields, TENSOR_NAME);
+
+        if number_of(&fields, TENSOR_DATA_TYPE) != Some(FLOAT) {
+            return Err(format!("{} is not a float tensor", name));
+        }
+
+        // repeated numbers can be written one by one, or packed together
+
+        let mut dims = Vec::new();
+
+        for (field, value) in &fields {
+            match value {
+                Value::Number(dim) if *field == TENSOR_DIMS => dims.push(*dim as usize),
+                Value::Bytes(packed) if *field == TENSOR_DIMS => {
+                    let mut position = 0;
+
+                    while position < packed.len() {
+                        dims.push(varint(packed, &mut position)? as usize);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let raw = bytes_of(&fields, TENSOR_RAW_DATA)
+            .next()
+            .or_else(|| bytes_of(&fields, TENSOR_FLOAT_DATA).next())
+            .unwrap_or_default();
+
+        let data: Vec<f32> = raw
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().expect("4 bytes")))
+            .collect();
+
+        if data.len() != dims.iter().product::<usize>() {
+            return Err(format!("{} has the wrong amount of data", name));
+        }
+
+        Ok((name, Tensor { dims, data }))
+    }
+
+    fn gemm(
+        inputs: &[String],
+        attributes: &HashMap<String, f64>,
+        tensors: &HashMap<String, Tensor>,
+    ) -> Result<Layer, String> {
+        let tensor = |index: usize| {
+            inputs
+                .get(index)
+                .and_then(|name| tensors.get(name))
+                .ok_or_else(|| format!("Gemm input {} is not in the file", index))
+        };
+
+        let weight = tensor(1)?;
+        let bias = tensor(2)?;
+
+        let attribute = |name: &str, default: f64| attributes.get(name).copied().unwrap_or(default);
+
+        if attribute("transA", 0.0) != 0.0 || weight.dims.len() != 2 {
+            return Err("only Gemm with a plain input and a 2D weight is supported".to_string());
+        }
+
+        let (alpha, beta) = (
+            attribute("alpha", 1.0) as f32,
+            attribute("beta", 1.0) as f32,
+        );
+
+        // PyTorch writes weights as [outputs, inputs] with transB set, otherwise they are the other
+        // way around
+
+        let (outputs, inputs) = if attribute("transB", 0.0) != 0.0 {
+            (weight.dims[0], weight.dims[1])
+        } else {
+            (weight.dims[1], weight.dims[0])
+        };
+
+        let mut weights = vec![0.0; outputs * inputs];
+
+        for output in 0..outputs {
+            for input in 0..inputs {
+                let index = if attribute("transB", 0.0) != 0.0 {
+                    output * inputs + input
+                } else {
+                    input * outputs + output
+                };
+
+                weights[output * inputs + input] = alpha * weight.data[index];
+            }
+        }
+
+        if bias.data.len() != outputs {
+            return Err("the Gemm bias doesn't match its weights".to_string());
+        }
+
+        Ok(Layer {
+            inputs,
+            outputs,
+            weights,
+            bias: bias.data.iter().map(|b| beta * b).collect(),
+            relu: false,
+        })
+    }
+
+    pub fn read(bytes: &[u8]) -> Result<Brnn, String> {
+        let model = fields(bytes)?;
+        let graph = bytes_of(&model, MODEL_GRAPH)
+            .next()
+            .ok_or("the file has no graph")?;
+        let graph = fields(graph)?;
+
+        let tensors = bytes_of(&graph, GRAPH_INITIALIZER)
+            .map(tensor)
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        let mut layers: Vec<Layer> = Vec::new();
+
+        // the nodes are stored in the order they run in, and each one feeds the next
+
+        for node in bytes_of(&graph, GRAPH_NODE) {
+            let node = fields(node)?;
+
+            let inputs: Vec<String> = bytes_of(&node, NODE_INPUT)
+                .map(|input| String::from_utf8_lossy(input).into_owned())
+                .collect();
+
+            let mut attributes = HashMap::new();
+
+            for attribute in bytes_of(&node, NODE_ATTRIBUTE) {
+                let attribute = fields(attribute)?;
+                let name = string_of(&attribute, ATTRIBUTE_NAME);
+
+                let value = match number_of(&attribute, ATTRIBUTE_FLOAT) {
+                    Some(bits) => f32::from_bits(bits as u32) as f64,
+                    None => number_of(&attribute, ATTRIBUTE_INT).unwrap_or(0) as i64 as f64,
+                };
+
+                attributes.insert(name, value);
+            }
+
+            match string_of(&node, NODE_OP_TYPE).as_str() {
+                "Gemm" => layers.push(gemm(&inputs, &attributes, &tensors)?),
+                "Relu" => {
+                    layers.last_mut().ok_or("Relu comes before any layer")?.relu = true;
+                }
+                other => return Err(format!("unsupported ONNX operator {}", other)),
+            }
+        }
+
+        Brnn::new(layers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_onnx_network() {
+        let network = Brnn::from_onnx(include_bytes!("../brnn.onnx")).unwrap();
+
+        let sizes: Vec<(usize, usize)> = network
+            .layers()
+            .iter()
+            .map(|layer| (layer.inputs, layer.outputs))
+            .collect();
+
+        assert_eq!(sizes.len(), 9);
+        assert_eq!(sizes[0], (16, 48));
+        assert_eq!(sizes[4], (48, 48));
+        assert_eq!(sizes[8], (48, 1));
+
+        assert!(network.layers()[..8].iter().all(|layer| layer.relu));
+        assert!(!network.layers()[8].relu);
+
+        // all zeros only goes through the biases, worked out with a separate ONNX reader
+
+        let output = network.forward(vec![0.0; FEATURES])[0];
+
+        assert!((output - 314.314_15).abs() < 1e-2, "{}", output);
+    }
+
+    #[test]
+    fn test_bundled_network() {
+        // the prediction is within a few percent of the simulation for the default settings
+
+        for population in [500, 1000, 3000] {
+            let mut settings = Settings::new();
+            settings.set_seed(15);
+            settings.set_population(population);
+
+            let simulation = Simulation::new(settings);
+
+            let predicted = simulation.predict();
+            let simulated = simulation.long().get_population() as f64;
+
+            assert!(
+                (predicted / simulated - 1.0).abs() < 0.1,
+                "{} predicted, {} simulated",
+                predicted,
+                simulated
+            );
+        }
+    }
+}
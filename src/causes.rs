@@ -0,0 +1,110 @@
+//! Causes of death for Beanreadings. When a bean dies, we record what it died of, so that we can
+//! tell what people are dying of under each scenario. The causes follow Our World in Data
+//! ([This Article](https://ourworldindata.org/causes-of-death)).
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CauseOfDeath {
+    CardiovascularDisease,
+    Cancer,
+    RespiratoryDisease,
+    DigestiveDisease,
+    Infection,
+    Neonatal,
+    Dimentia,
+    Diabetes,
+    DiarrhealDisease,
+    LiverDisease,
+    KidneyDisease,
+    Malnutrition,
+    OldAge, // the bean lived past the maximum age
+}
+
+impl CauseOfDeath {
+    /// How many causes of death there are.
+    pub const COUNT: usize = 13;
+
+    /// Every cause of death, in the same order as their indices.
+    pub const ALL: [CauseOfDeath; CauseOfDeath::COUNT] = [
+        CauseOfDeath::CardiovascularDisease,
+        CauseOfDeath::Cancer,
+        CauseOfDeath::RespiratoryDisease,
+        CauseOfDeath::DigestiveDisease,
+        CauseOfDeath::Infection,
+        CauseOfDeath::Neonatal,
+        CauseOfDeath::Dimentia,
+        CauseOfDeath::Diabetes,
+        CauseOfDeath::DiarrhealDisease,
+        CauseOfDeath::LiverDisease,
+        CauseOfDeath::KidneyDisease,
+        CauseOfDeath::Malnutrition,
+        CauseOfDeath::OldAge,
+    ];
+
+    /// The position of this cause in `CauseOfDeath::ALL`, used to index per-cause counts.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Short machine readable name, used for series names and file headers.
+    pub fn key(self) -> &'static str {
+        match self {
+            CauseOfDeath::CardiovascularDisease => "cardiovascular_disease",
+            CauseOfDeath::Cancer => "cancer",
+            CauseOfDeath::RespiratoryDisease => "respiratory_disease",
+            CauseOfDeath::DigestiveDisease => "digestive_disease",
+            CauseOfDeath::Infection => "infection",
+            CauseOfDeath::Neonatal => "neonatal",
+            CauseOfDeath::Dimentia => "dimentia",
+            CauseOfDeath::Diabetes => "diabetes",
+            CauseOfDeath::DiarrhealDisease => "diarrheal_disease",
+            CauseOfDeath::LiverDisease => "liver_disease",
+            CauseOfDeath::KidneyDisease => "kidney_disease",
+            CauseOfDeath::Malnutrition => "malnutrition",
+            CauseOfDeath::OldAge => "old_age",
+        }
+    }
+
+    /// Looks a cause up by its `key`.
+    pub fn from_key(key: &str) -> Option<CauseOfDeath> {
+        CauseOfDeath::ALL
+            .into_iter()
+            .find(|cause| cause.key() == key)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CauseOfDeath::CardiovascularDisease => "Cardiovascular disease",
+            CauseOfDeath::Cancer => "Cancer",
+            CauseOfDeath::RespiratoryDisease => "Respiratory disease",
+            CauseOfDeath::DigestiveDisease => "Digestive disease",
+            CauseOfDeath::Infection => "Infection",
+            CauseOfDeath::Neonatal => "Neonatal",
+            CauseOfDeath::Dimentia => "Dementia",
+            CauseOfDeath::Diabetes => "Diabetes",
+            CauseOfDeath::DiarrhealDisease => "Diarrheal disease",
+            CauseOfDeath::LiverDisease => "Liver disease",
+            CauseOfDeath::KidneyDisease => "Kidney disease",
+            CauseOfDeath::Malnutrition => "Malnutrition",
+            CauseOfDeath::OldAge => "Old age",
+        }
+    }
+}
+
+/// Human readable name of a cause of death, for labelling charts.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cause_of_death_name(cause: CauseOfDeath) -> String {
+    cause.name().to_string()
+}
+
+/// Names of every cause of death, in the same order as the per-cause counts in the results.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cause_of_death_names() -> Vec<String> {
+    CauseOfDeath::ALL
+        .iter()
+        .map(|cause| cause.name().to_string())
+        .collect()
+}
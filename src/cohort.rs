@@ -0,0 +1,871 @@
+//! Cohort-component engine for Beanreadings. Instead of simulating every bean, we keep track of
+//! how many beans there are of each age, sex and risk profile, and move expected numbers of them
+//! between cohorts every year. The work per year doesn't depend on the size of the population, so
+//! this can run whole countries in milliseconds.
+//!
+//! The risk profiles are a sample of factors drawn from the settings, exactly like the factors of
+//! the beans in the agent engine, and the hazards come from `Bean::risks`, so both engines share
+//! the same mortality model. Every profile also has a newborn version with no habits, because
+//! that is how newborn beans start out, and they move to the profile itself when they come of age.
+//!
+//! Policies that change how common a habit is are followed by moving adults between profiles
+//! with and without the habit, rather than changing the profiles themselves. This matches the
+//! agent engine on average, but a habit can only become more common in an age group that has
+//! some beans with it already. Beans that quit move to profiles that never had the habit, so
+//! they don't keep any of its risk like quitters in the agent engine do. Habit transitions aren't
+//! followed at all, as they depend on when each bean quit, so `try_cohort` turns them away.
+
+use crate::age::AgeDistribution;
+use crate::bean::*;
+use crate::causes::CauseOfDeath;
+use crate::error::SimulationError;
+use crate::factors::{Factors, Habit};
+use crate::rng::seeded;
+use crate::types::*;
+use crate::wealth::{quintile_shares, QUINTILES};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// How many risk profiles are sampled when none are asked for.
+pub const DEFAULT_PROFILES: u32 = 128;
+
+const SEXES: [Sex; 2] = [Sex::Female, Sex::Male];
+
+/// Expected numbers for one year of the cohort engine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CohortYear {
+    pub deaths_by_cause: [f64; CauseOfDeath::COUNT],
+    pub deaths_by_sex: [[f64; CauseOfDeath::COUNT]; 2], // by `Sex::index`
+    pub deaths_by_quintile: [f64; QUINTILES],
+    pub exposure_by_quintile: [f64; QUINTILES],
+    pub deaths_by_age: Vec<f64>, // women then men, for every age up to max_age + 1
+    pub exposure_by_age: Vec<f64>, // alive at the start of the year, in the same order
+}
+
+/// A population split into cohorts by sex, risk profile and age.
+pub struct CohortModel {
+    settings: Settings,
+
+    effective: Settings, // the settings in effect last year, after applying the policies
+
+    year: u32,
+
+    profiles: [Vec<Factors>; 2], // each sex's sampled profiles followed by their newborn versions
+
+    ages: usize, // ages go from 0 to max_age + 1, when everyone dies of old age
+
+    counts: Vec<f64>, // expected number of beans in each cohort, see `index`
+
+    death_chance: Vec<f64>, // chance of dying this year, for each cohort
+
+    cause_shares: Vec<[f64; CauseOfDeath::COUNT]>, // how deaths in each cohort split by cause
+
+    fertility: Vec<f64>,
+}
+
+impl CohortModel {
+    /// Sets up the starting population, spread over `profiles` risk profiles sampled with the
+    /// seed in the settings.
+    pub fn new(settings: Settings, profiles: u32) -> CohortModel {
+        let ages = settings.age_distribution();
+
+        CohortModel::with_ages(settings, profiles, ages.as_ref())
+    }
+
+    /// Sets up the starting population with its ages and sexes following `ages`.
+    pub fn with_ages(
+        settings: Settings,
+        profiles: u32,
+        age_distribution: &dyn AgeDistribution,
+    ) -> CohortModel {
+        let profiles = profiles.max(1) as usize;
+
+        let mut rng = seeded(settings.seed);
+
+        let effective = settings.at_year(0);
+
+        // each sex has its own profiles, as habits are more common in one than the other
+
+        let sampled = SEXES.map(|sex| {
+            let mut sampled: Vec<Factors> = (0..profiles)
+                .map(|_| Bean::with_age(&effective, 0, sex, &mut rng).get_factors())
+                .collect();
+
+            let newborns: Vec<Factors> = sampled
+                .iter()
+                .map(|factors| {
+                    let mut factors = factors.clone();
+                    factors.clear_habits();
+                    factors
+                })
+                .collect();
+
+            sampled.extend(newborns);
+            sampled
+        });
+
+        let ages = settings.max_age as usize + 2;
+
+        let mut model = CohortModel {
+            fertility: effective.fertility_rates(),
+            effective,
+            year: 0,
+            profiles: sampled,
+            ages,
+            counts: Vec::new(),
+            death_chance: Vec::new(),
+            cause_shares: Vec::new(),
+            settings,
+        };
+
+        model.counts = vec![0.0; model.cells()];
+        model.work_out_risks();
+
+        // the starting population has the same ages and sexes as the agent engine, and each sex
+        // is spread evenly over its sampled profiles, or their newborn versions for beans too
+        // young for habits
+
+        let per_cohort = model.settings.population as f64 / profiles as f64;
+
+        for (sex, chances) in SEXES
+            .map(|sex| age_distribution.chances(sex))
+            .iter()
+            .enumerate()
+        {
+            for (age, chance) in chances.iter().enumerate() {
+                let age = age.min(ages - 1);
+
+                for profile in model.sampled_profiles(age < ADULT_AGE as usize) {
+                    let cohort = model.index(sex, profile, age);
+
+                    model.counts[cohort] += per_cohort * chance;
+                }
+            }
+        }
+
+        model
+    }
+
+    /// How many profiles each sex has, counting the newborn versions.
+    fn per_sex(&self) -> usize {
+        self.profiles[0].len()
+    }
+
+    fn cells(&self) -> usize {
+        SEXES.len() * self.per_sex() * self.ages
+    }
+
+    fn index(&self, sex: usize, profile: usize, age: usize) -> usize {
+        (sex * self.per_sex() + profile) * self.ages + age
+    }
+
+    /// The sampled profiles of a sex, or their newborn versions.
+    fn sampled_profiles(&self, newborn: bool) -> std::ops::Range<usize> {
+        let sampled = self.per_sex() / 2;
+        let start = newborn as usize * sampled;
+
+        start..start + sampled
+    }
+
+    /// Works out the chance of dying, and what of, for every cohort. These only depend on the
+    /// settings, so we do it once instead of every year.
+    fn work_out_risks(&mut self) {
+        self.death_chance = vec![0.0; self.cells()];
+        self.cause_shares = vec![[0.0; CauseOfDeath::COUNT]; self.cells()];
+
+        for (sex_index, sex) in SEXES.into_iter().enumerate() {
+            for profile in 0..self.per_sex() {
+                for age in 0..self.ages {
+                    // profiles don't age, so smokers get the pack-years for each age like the
+                    // starting population of the agent engine
+
+                    let mut factors = self.profiles[sex_index][profile].clone();
+
+                    if factors.smokes_cigarettes {
+                        factors.exposures.pack_years = factors.exposures.pack_years_at(age as u32);
+                    }
+
+                    let bean = Bean::from_parts(age as u32, sex, factors);
+
+                    let risks = bean.risks(&self.effective);
+                    let total: f64 = risks.iter().sum();
+
+                    let cohort = self.index(sex_index, profile, age);
+
+                    self.death_chance[cohort] = total.min(1.0);
+
+                    if total > 0.0 {
+                        for cause in CauseOfDeath::ALL {
+                            self.cause_shares[cohort][cause.index()] = risks[cause.index()] / total;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Simulates one year, in the same order as the agent engine: births, then deaths, then
+    /// everyone ages and the newborns join.
+    pub fn step(&mut self) -> CohortYear {
+        let effective = self.settings.at_year(self.year);
+
+        if effective != self.effective {
+            self.follow_policy(effective);
+        }
+
+        self.year += 1;
+
+        // births come from the women
+
+        let mut births = 0.0;
+
+        for profile in 0..self.per_sex() {
+            for age in 0..self.ages.min(self.fertility.len()) {
+                births += self.counts[self.index(0, profile, age)] * self.fertility[age];
+            }
+        }
+
+        births *= 1.0 + self.effective.twin_rate;
+
+        let mut year = CohortYear {
+            deaths_by_cause: [0.0; CauseOfDeath::COUNT],
+            deaths_by_sex: [[0.0; CauseOfDeath::COUNT]; 2],
+            deaths_by_quintile: [0.0; QUINTILES],
+            exposure_by_quintile: [0.0; QUINTILES],
+            deaths_by_age: vec![0.0; SEXES.len() * self.ages],
+            exposure_by_age: vec![0.0; SEXES.len() * self.ages],
+        };
+
+        // by profile, women then men
+
+        let mut alive = vec![0.0; SEXES.len() * self.per_sex()];
+        let mut died = vec![0.0; SEXES.len() * self.per_sex()];
+
+        for sex in 0..SEXES.len() {
+            for profile in 0..self.per_sex() {
+                let both = sex * self.per_sex() + profile;
+
+                for age in 0..self.ages {
+                    let cohort = self.index(sex, profile, age);
+                    let deaths = self.counts[cohort] * self.death_chance[cohort];
+
+                    for (cause, share) in self.cause_shares[cohort].into_iter().enumerate() {
+                        year.deaths_by_cause[cause] += deaths * share;
+                        year.deaths_by_sex[sex][cause] += deaths * share;
+                    }
+
+                    year.deaths_by_age[sex * self.ages + age] += deaths;
+                    year.exposure_by_age[sex * self.ages + age] += self.counts[cohort];
+
+                    alive[both] += self.counts[cohort];
+                    died[both] += deaths;
+
+                    self.counts[cohort] -= deaths;
+                }
+            }
+        }
+
+        // wealth quintiles are worked out from everybody alive at the start of the year
+
+        let wealths: Vec<f64> = self
+            .profiles
+            .iter()
+            .flatten()
+            .map(|factors| factors.wealth.value)
+            .collect();
+
+        for (profile, shares) in quintile_shares(&wealths, &alive).into_iter().enumerate() {
+            for (quintile, share) in shares.into_iter().enumerate() {
+                year.exposure_by_quintile[quintile] += alive[profile] * share;
+                year.deaths_by_quintile[quintile] += died[profile] * share;
+            }
+        }
+
+        // everyone ages by a year, nobody survives past the oldest age
+
+        for sex in 0..SEXES.len() {
+            for profile in 0..self.per_sex() {
+                let start = self.index(sex, profile, 0);
+
+                self.counts
+                    .copy_within(start..start + self.ages - 1, start + 1);
+                self.counts[start] = 0.0;
+            }
+        }
+
+        self.come_of_age();
+
+        let male = self.effective.male_birth_probability();
+
+        for (sex, share) in [1.0 - male, male].into_iter().enumerate() {
+            let profiles = self.sampled_profiles(true);
+            let per_profile = births * share / profiles.len() as f64;
+
+            for profile in profiles {
+                let newborns = self.index(sex, profile, 0);
+
+                self.counts[newborns] += per_profile;
+            }
+        }
+
+        year
+    }
+
+    /// Moves the beans born in the simulation that have just come of age from the newborn
+    /// versions of the profiles to the profiles themselves, like `Bean::come_of_age`. The profiles
+    /// were sampled at the rates of the first year, so the beans are then moved between them to
+    /// match the rates in effect now.
+    fn come_of_age(&mut self) {
+        let age = ADULT_AGE as usize;
+
+        if age >= self.ages {
+            return;
+        }
+
+        let sampled_at = self.settings.at_year(0);
+
+        for (sex, sex_of) in SEXES.into_iter().enumerate() {
+            let mut counts = vec![0.0; self.per_sex()];
+
+            for (newborn, sampled) in self
+                .sampled_profiles(true)
+                .zip(self.sampled_profiles(false))
+            {
+                let cohort = self.index(sex, newborn, age);
+
+                counts[sampled] = self.counts[cohort];
+                self.counts[cohort] = 0.0;
+            }
+
+            for habit in Habit::ALL {
+                if let (Some(before), Some(after)) = (
+                    habit.rate_for(&sampled_at, sex_of),
+                    habit.rate_for(&self.effective, sex_of),
+                ) {
+                    shift_habit(&mut counts, &self.profiles[sex], habit, before, after);
+                }
+            }
+
+            for (profile, count) in counts.into_iter().enumerate() {
+                let cohort = self.index(sex, profile, age);
+
+                self.counts[cohort] += count;
+            }
+        }
+    }
+
+    /// Follows a change in the settings from a policy. Adults move between profiles so that the
+    /// same share of them quit or start each habit as in `Bean::follow_policy`, and every
+    /// profile's diet moves with the settings.
+    fn follow_policy(&mut self, effective: Settings) {
+        let before = &self.effective;
+
+        for habit in Habit::ALL {
+            for (sex, rates) in SEXES
+                .map(|sex| (habit.rate_for(before, sex), habit.rate_for(&effective, sex)))
+                .into_iter()
+                .enumerate()
+            {
+                let (Some(before), Some(after)) = rates else {
+                    continue;
+                };
+
+                // anyone can quit, but only adults start
+
+                let first_age = match after.partial_cmp(&before) {
+                    Some(std::cmp::Ordering::Less) => 0,
+                    Some(std::cmp::Ordering::Greater) => ADULT_AGE as usize,
+                    _ => continue,
+                };
+
+                for age in first_age..self.ages {
+                    let cohorts: Vec<usize> = (0..self.per_sex())
+                        .map(|profile| self.index(sex, profile, age))
+                        .collect();
+
+                    let mut counts: Vec<f64> =
+                        cohorts.iter().map(|&cohort| self.counts[cohort]).collect();
+
+                    shift_habit(&mut counts, &self.profiles[sex], habit, before, after);
+
+                    for (cohort, count) in cohorts.into_iter().zip(counts) {
+                        self.counts[cohort] = count;
+                    }
+                }
+            }
+        }
+
+        for factors in self.profiles.iter_mut().flatten() {
+            factors.shift_diet(before, &effective);
+        }
+
+        self.fertility = effective.fertility_rates();
+        self.effective = effective;
+
+        self.work_out_risks();
+    }
+
+    /// The expected number of beans alive.
+    pub fn population(&self) -> f64 {
+        self.counts.iter().sum()
+    }
+
+    /// The expected number of beans of each sex alive, by `Sex::index`.
+    pub fn population_by_sex(&self) -> [f64; 2] {
+        let per_sex = self.per_sex() * self.ages;
+
+        [0, 1].map(|sex| self.counts[sex * per_sex..(sex + 1) * per_sex].iter().sum())
+    }
+
+    /// The expected share of the population with each habit, by `Habit::index`.
+    pub fn prevalence(&self) -> [f64; Habit::COUNT] {
+        let mut with = [0.0; Habit::COUNT];
+
+        for sex in 0..SEXES.len() {
+            for (profile, factors) in self.profiles[sex].iter().enumerate() {
+                let start = self.index(sex, profile, 0);
+                let count: f64 = self.counts[start..start + self.ages].iter().sum();
+
+                for habit in Habit::ALL {
+                    if factors.has(habit) {
+                        with[habit.index()] += count;
+                    }
+                }
+            }
+        }
+
+        let population = self.population();
+
+        with.map(|count| {
+            if population > 0.0 {
+                count / population
+            } else {
+                0.0
+            }
+        })
+    }
+
+    /// Runs for `settings.years` years, rounding the expected numbers into a normal result. The
+    /// parts of every count add up to its rounded total, and deaths that get rounded away one
+    /// year are carried over to the next, so rare causes still add up over the run.
+    pub fn run(mut self) -> SimulationResult {
+        let mut result = SimulationResult::default();
+
+        let mut deaths_by_sex = CarriedRounding::new(SEXES.len() * CauseOfDeath::COUNT);
+        let mut deaths_by_quintile = CarriedRounding::new(QUINTILES);
+
+        for _ in 0..self.settings.years {
+            let year = self.step();
+
+            // deaths by cause are the sum of both sexes, so they add up in every direction
+
+            let by_sex = deaths_by_sex.next(&year.deaths_by_sex.concat(), None);
+            let (women, men) = by_sex.split_at(CauseOfDeath::COUNT);
+            let total: u32 = by_sex.iter().sum();
+
+            result.deaths_by_sex.extend(&by_sex);
+            result
+                .deaths_by_cause
+                .extend(women.iter().zip(men).map(|(women, men)| women + men));
+            result
+                .deaths_by_quintile
+                .extend(deaths_by_quintile.next(&year.deaths_by_quintile, Some(total)));
+
+            let exposure = year.exposure_by_quintile.iter().sum::<f64>().round() as u32;
+
+            result
+                .exposure_by_quintile
+                .extend(round_to_total(&year.exposure_by_quintile, exposure));
+
+            let population = self.population().round() as u32;
+
+            result.population_curve.push(population);
+            result
+                .population_by_sex
+                .extend(round_to_total(&self.population_by_sex(), population));
+            result.prevalence.extend(self.prevalence());
+
+            // life tables are worked out from these, and rounding them would lose every death
+            // at ages where fewer than half a bean is expected to die
+
+            result.deaths_by_age.extend(year.deaths_by_age);
+            result.exposure_by_age.extend(year.exposure_by_age);
+        }
+
+        result.population = self.population().round() as u32;
+
+        result
+    }
+}
+
+/// Moves beans of one age and sex between the profiles with and without a habit, so that the same
+/// share of them quit or start it as in `Bean::follow_policy` when its rate goes from `before` to
+/// `after`. `counts` are by profile.
+fn shift_habit(counts: &mut [f64], profiles: &[Factors], habit: Habit, before: f64, after: f64) {
+    let mut with = 0.0;
+    let mut without = 0.0;
+
+    for (count, factors) in counts.iter().zip(profiles) {
+        if factors.has(habit) {
+            with += count;
+        } else {
+            without += count;
+        }
+    }
+
+    if with <= 0.0 || without <= 0.0 || after == before {
+        return; // nobody to move between
+    }
+
+    let (with_after, without_after) = if after < before {
+        let kept = with * after / before;
+
+        (kept, without + with - kept)
+    } else {
+        let kept = without * (1.0 - (after - before) / (1.0 - before));
+
+        (with + without - kept, kept)
+    };
+
+    for (count, factors) in counts.iter_mut().zip(profiles) {
+        *count *= if factors.has(habit) {
+            with_after / with
+        } else {
+            without_after / without
+        };
+    }
+}
+
+/// Rounds `parts` to whole beans that add up to `total`, giving the beans that are left over to
+/// the parts that lost the most to rounding down (largest remainder rounding).
+fn round_to_total(parts: &[f64], total: u32) -> Vec<u32> {
+    let mut rounded: Vec<u32> = parts.iter().map(|part| part.max(0.0) as u32).collect();
+
+    let remainder = |i: usize| parts[i] - rounded[i] as f64;
+
+    let mut order: Vec<usize> = (0..parts.len()).collect();
+    order.sort_by(|&a, &b| remainder(b).total_cmp(&remainder(a)));
+
+    let mut given: u32 = rounded.iter().sum();
+
+    // parts below 0 can leave too many beans given, so those come off the smallest remainders
+
+    while given > total {
+        for &i in order.iter().rev() {
+            if given > total && rounded[i] > 0 {
+                rounded[i] -= 1;
+                given -= 1;
+            }
+        }
+    }
+
+    for &i in order.iter().cycle().take((total - given) as usize) {
+        rounded[i] += 1;
+    }
+
+    rounded
+}
+
+/// Rounds counts that come in a year at a time, keeping track of what every part was owed, so
+/// that what is rounded away one year is made up for in a later one.
+struct CarriedRounding {
+    expected: Vec<f64>, // the unrounded counts so far
+
+    given: Vec<u32>, // the rounded counts so far
+}
+
+impl CarriedRounding {
+    fn new(parts: usize) -> CarriedRounding {
+        CarriedRounding {
+            expected: vec![0.0; parts],
+            given: vec![0; parts],
+        }
+    }
+
+    /// Rounds the next year's counts. Their total is the rounded total so far minus what was
+    /// already given, unless it is set.
+    fn next(&mut self, parts: &[f64], total: Option<u32>) -> Vec<u32> {
+        for (expected, part) in self.expected.iter_mut().zip(parts) {
+            *expected += part;
+        }
+
+        let owed: Vec<f64> = self
+            .expected
+            .iter()
+            .zip(&self.given)
+            .map(|(&expected, &given)| expected - given as f64)
+            .collect();
+
+        let given: u32 = self.given.iter().sum();
+
+        let total = total.unwrap_or_else(|| {
+            (self.expected.iter().sum::<f64>().round() as u32).saturating_sub(given)
+        });
+
+        let rounded = round_to_total(&owed, total);
+
+        for (given, rounded) in self.given.iter_mut().zip(&rounded) {
+            *given += rounded;
+        }
+
+        rounded
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Simulation {
+    /// Runs the cohort engine instead of simulating every bean. The result is the expected
+    /// outcome rather than one random one, and takes the same time for any population. Like
+    /// `long`, the settings are not checked, and habit transitions are left out as if they
+    /// weren't set. Use `try_cohort` to have both turned away instead.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn cohort(&self) -> SimulationResult {
+        self.cohort_with_profiles(DEFAULT_PROFILES)
+    }
+
+    /// Runs the cohort engine with a chosen number of risk profiles. More profiles follow the
+    /// spread of factors more closely, but are slower. Habit transitions are left out, like in
+    /// `cohort`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn cohort_with_profiles(&self, profiles: u32) -> SimulationResult {
+        CohortModel::with_ages(
+            self.settings.clone(),
+            profiles,
+            self.age_distribution().as_ref(),
+        )
+        .run()
+    }
+
+    /// Checks the settings, then runs the cohort engine. Habit transitions need every bean's
+    /// history, so settings with them are turned away.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn try_cohort(&self) -> Result<SimulationResult, SimulationError> {
+        self.settings.validate()?;
+
+        if self.settings.habit_transitions().is_some() {
+            return Err(SimulationError::invalid(
+                "habit_transitions",
+                "can't be followed by the cohort engine, run the agent engine instead",
+            ));
+        }
+
+        Ok(self.cohort())
+    }
+
+    /// Checks the cohort engine against an ensemble of the agent engine with the same settings.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn cross_check(&self, replicates: u32) -> CrossCheck {
+        let cohort = self.cohort();
+        let ensemble = self.ensemble(replicates, Vec::new());
+
+        let agents = ensemble.series("population");
+
+        CrossCheck {
+            replicates,
+            cohort: cohort
+                .get_population_curve()
+                .iter()
+                .map(|&n| n as f64)
+                .collect(),
+            agent_mean: agents.map(|s| s.mean.clone()).unwrap_or_default(),
+            agent_std_dev: agents.map(|s| s.std_dev.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+/// A comparison of the population curves of the cohort engine and the agent engine. If both
+/// engines agree, the cohort curve should be within a few standard errors of the agent mean.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone)]
+pub struct CrossCheck {
+    replicates: u32,
+    cohort: Vec<f64>,
+    agent_mean: Vec<f64>,
+    agent_std_dev: Vec<f64>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CrossCheck {
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_cohort(&self) -> Vec<f64> {
+        self.cohort.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_agent_mean(&self) -> Vec<f64> {
+        self.agent_mean.clone()
+    }
+
+    /// How many standard errors of the agent mean the cohort engine is away from it, per year.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_z_scores(&self) -> Vec<f64> {
+        let n = (self.replicates as f64).sqrt();
+
+        self.cohort
+            .iter()
+            .zip(&self.agent_mean)
+            .zip(&self.agent_std_dev)
+            .map(|((cohort, mean), std_dev)| {
+                let error = std_dev / n;
+
+                if error > 0.0 {
+                    (cohort - mean) / error
+                } else if cohort == mean {
+                    0.0
+                } else {
+                    f64::INFINITY
+                }
+            })
+            .collect()
+    }
+
+    /// Difference between the engines as a fraction of the agent mean, per year.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_relative_difference(&self) -> Vec<f64> {
+        self.cohort
+            .iter()
+            .zip(&self.agent_mean)
+            .map(|(cohort, mean)| (cohort - mean) / mean.max(1.0))
+            .collect()
+    }
+
+    /// Whether every year is within `z_limit` standard errors, or within 1% of the population,
+    /// whichever is looser. The cohort engine uses a sample of risk profiles, so it can be off by
+    /// a little even when the ensemble is very large.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn agrees(&self, z_limit: f64) -> bool {
+        self.get_z_scores()
+            .iter()
+            .zip(self.get_relative_difference())
+            .all(|(z, relative)| z.abs() <= z_limit || relative.abs() <= 0.01)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cohort_matches_agents() {
+        let mut settings = Settings::new();
+        settings.set_seed(21);
+        settings.set_population(2000);
+
+        let check = Simulation::new(settings).cross_check(16);
+
+        assert!(check.agrees(4.0));
+    }
+
+    #[test]
+    fn test_cohort_follows_policies() {
+        let mut settings = Settings::new();
+        settings.set_seed(22);
+        settings.set_population(2000);
+        settings
+            .set_policies_json(
+                r#"[
+                    { "parameter": "hard_drugger", "kind": "step", "year": 2, "value": 0.0 },
+                    { "parameter": "smokers", "kind": "ramp", "start": 0, "end": 5, "from": 0.2, "to": 0.4 },
+                    { "parameter": "vitamins", "kind": "step", "year": 3, "value": 1.2 }
+                ]"#,
+            )
+            .unwrap();
+
+        assert!(Simulation::new(settings).cross_check(16).agrees(4.0));
+    }
+
+    #[test]
+    fn test_cohort_scales() {
+        let mut settings = Settings::new();
+        settings.set_seed(4);
+        settings.set_population(300_000_000);
+
+        let result = Simulation::new(settings.clone()).cohort();
+
+        let deaths: u32 = result.get_total_deaths().iter().sum();
+
+        assert!(deaths > settings.population / 100);
+        assert!(result.get_population() > settings.population / 2);
+    }
+
+    #[test]
+    fn test_default_population_is_stable() {
+        // the default fertility rate replaces the population, so it neither dies out nor takes
+        // off over a long run
+
+        let mut settings = Settings::new();
+        settings.set_seed(5);
+        settings.set_population(1_000_000);
+        settings.set_years(150);
+
+        let population = Simulation::new(settings.clone()).cohort().get_population();
+        let ratio = population as f64 / settings.population as f64;
+
+        assert!((0.8..1.25).contains(&ratio), "{}", population);
+    }
+
+    #[test]
+    fn test_cohort_rounding() {
+        assert_eq!(round_to_total(&[0.3, 0.45, 1.25], 2), vec![0, 1, 1]);
+        assert_eq!(round_to_total(&[0.6, 0.6, 0.6], 1), vec![1, 0, 0]);
+
+        // a small population expects less than half a death of most causes every year, which
+        // used to be rounded away
+
+        let mut settings = Settings::new();
+        settings.set_population(1000);
+        settings.set_years(30);
+
+        let mut agents = 0.0;
+
+        for seed in [3, 4, 5, 6] {
+            settings.set_seed(seed);
+
+            let deaths: u32 = Simulation::new(settings.clone())
+                .long()
+                .get_total_deaths()
+                .iter()
+                .sum();
+
+            agents += deaths as f64 / 4.0;
+        }
+
+        let result = Simulation::new(settings).cohort();
+
+        let total: u32 = result.get_total_deaths().iter().sum();
+        let expected: f64 = result.deaths_by_age.iter().sum();
+
+        assert!((total as f64 - expected).abs() <= 1.0);
+        assert!(
+            (total as f64 / agents - 1.0).abs() < 0.1,
+            "{} {}",
+            total,
+            agents
+        );
+
+        // and every way of splitting them adds up
+
+        let quintiles: u32 = (0..QUINTILES)
+            .map(|quintile| result.get_deaths_by_quintile(quintile).iter().sum::<u32>())
+            .sum();
+
+        assert_eq!(quintiles, total);
+
+        for (year, &population) in result.get_population_curve().iter().enumerate() {
+            let by_sex: Vec<u32> = SEXES
+                .iter()
+                .map(|&sex| result.get_population_curve_by_sex(sex)[year])
+                .collect();
+
+            assert_eq!(by_sex[0] + by_sex[1], population);
+
+            for cause in CauseOfDeath::ALL {
+                assert_eq!(
+                    result.get_deaths_by_cause_and_sex(cause, Sex::Female)[year]
+                        + result.get_deaths_by_cause_and_sex(cause, Sex::Male)[year],
+                    result.get_deaths_by_cause(cause)[year]
+                );
+            }
+        }
+    }
+}
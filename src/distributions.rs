@@ -0,0 +1,1190 @@
+//! Probability distributions for Beanreadings. Every continuous distribution implements
+//! `ContinuousDistribution`, so the model can swap one shape for another:
+//!
+//! - `NormalDistribution`, and `TruncatedNormal` for things that can't go below zero, like diets
+//! - `LogNormal`, for skewed amounts like wealth
+//! - `Gompertz` and `Weibull`, for survival times
+//! - `Gamma` and `Beta`, for positive amounts and for shares between 0 and 1
+//!
+//! `Poisson` counts events, so it implements `DiscreteDistribution` instead. The same methods are
+//! exported to JavaScript, with `samples` in place of `sample` so that a seed can be passed in.
+
+use crate::error::SimulationError;
+use crate::rng::seeded;
+use rand::distributions::Open01;
+use rand::{Rng, RngCore};
+use std::f64::consts::{PI, SQRT_2};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// A distribution over real numbers.
+pub trait ContinuousDistribution {
+    /// Probability density function.
+    fn pdf(&self, x: f64) -> f64;
+
+    /// Cumulative distribution function, the chance of a value of at most `x`.
+    fn cdf(&self, x: f64) -> f64;
+
+    /// The inverse of `cdf`: the value with a chance `p` of being at least as large as a sample.
+    fn quantile(&self, p: f64) -> f64;
+
+    fn mean(&self) -> f64;
+
+    fn variance(&self) -> f64;
+
+    /// Draws a value, by default by inverting the CDF.
+    fn sample(&self, rng: &mut dyn RngCore) -> f64 {
+        self.quantile(rng.sample(Open01))
+    }
+}
+
+/// A distribution over counts.
+pub trait DiscreteDistribution {
+    /// Probability mass function, the chance of exactly `k`.
+    fn pmf(&self, k: u64) -> f64;
+
+    /// The chance of at most `k`.
+    fn cdf(&self, k: u64) -> f64;
+
+    /// The smallest count with a `cdf` of at least `p`.
+    fn quantile(&self, p: f64) -> u64;
+
+    fn mean(&self) -> f64;
+
+    fn variance(&self) -> f64;
+
+    fn sample(&self, rng: &mut dyn RngCore) -> u64 {
+        self.quantile(rng.sample(Open01))
+    }
+}
+
+/// Exports the methods of `ContinuousDistribution` to JavaScript, which doesn't know about traits.
+macro_rules! export_continuous {
+    ($($distribution:ty),*) => {$(
+        #[cfg_attr(feature = "wasm", wasm_bindgen)]
+        impl $distribution {
+            #[cfg_attr(feature = "wasm", wasm_bindgen)]
+            pub fn pdf(&self, x: f64) -> f64 {
+                ContinuousDistribution::pdf(self, x)
+            }
+
+            #[cfg_attr(feature = "wasm", wasm_bindgen)]
+            pub fn cdf(&self, x: f64) -> f64 {
+                ContinuousDistribution::cdf(self, x)
+            }
+
+            #[cfg_attr(feature = "wasm", wasm_bindgen)]
+            pub fn quantile(&self, p: f64) -> f64 {
+                ContinuousDistribution::quantile(self, p)
+            }
+
+            #[cfg_attr(feature = "wasm", wasm_bindgen)]
+            pub fn mean(&self) -> f64 {
+                ContinuousDistribution::mean(self)
+            }
+
+            #[cfg_attr(feature = "wasm", wasm_bindgen)]
+            pub fn variance(&self) -> f64 {
+                ContinuousDistribution::variance(self)
+            }
+
+            /// `count` samples drawn with `seed`, e.g. for drawing a histogram.
+            #[cfg_attr(feature = "wasm", wasm_bindgen)]
+            pub fn samples(&self, count: u32, seed: u64) -> Vec<f64> {
+                let mut rng = seeded(seed);
+
+                (0..count)
+                    .map(|_| ContinuousDistribution::sample(self, &mut rng))
+                    .collect()
+            }
+        }
+    )*};
+}
+
+export_continuous!(
+    NormalDistribution,
+    TruncatedNormal,
+    LogNormal,
+    Gompertz,
+    Weibull,
+    Gamma,
+    Beta
+);
+
+fn positive(field: &str, value: f64) -> Result<f64, SimulationError> {
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(SimulationError::invalid(
+            field,
+            format!("should be positive, got {}", value),
+        ))
+    }
+}
+
+fn finite(field: &str, value: f64) -> Result<f64, SimulationError> {
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(SimulationError::invalid(
+            field,
+            format!("should be a number, got {}", value),
+        ))
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormalDistribution {
+    mean: f64,
+    standard_deviation: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NormalDistribution {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(mean: f64, standard_deviation: f64) -> NormalDistribution {
+        NormalDistribution {
+            mean,
+            standard_deviation,
+        }
+    }
+
+    /// The old name of `quantile`.
+    pub fn quartile(&self, x: f64) -> f64 {
+        ContinuousDistribution::quantile(self, x)
+    }
+}
+
+impl ContinuousDistribution for NormalDistribution {
+    fn pdf(&self, x: f64) -> f64 {
+        // 1 / (σ * sqrt(2π)) * e^(-1/2 * ((x - μ) / σ)^2)
+
+        standard_normal_pdf((x - self.mean) / self.standard_deviation) / self.standard_deviation
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        standard_normal_cdf((x - self.mean) / self.standard_deviation)
+    }
+
+    fn quantile(&self, p: f64) -> f64 {
+        self.mean + self.standard_deviation * standard_normal_quantile(p)
+    }
+
+    fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    fn variance(&self) -> f64 {
+        self.standard_deviation.powi(2)
+    }
+}
+
+/// A normal distribution cut off below `lower` and above `upper`, with the chance that was cut off
+/// spread over what is left. Either bound can be infinite.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TruncatedNormal {
+    normal: NormalDistribution, // before it was cut off
+    lower: f64,
+    upper: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl TruncatedNormal {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        mean: f64,
+        standard_deviation: f64,
+        lower: f64,
+        upper: f64,
+    ) -> Result<TruncatedNormal, SimulationError> {
+        let normal = NormalDistribution::new(
+            finite("mean", mean)?,
+            positive("standard_deviation", standard_deviation)?,
+        );
+
+        if lower.is_nan() || upper.is_nan() || lower >= upper {
+            return Err(SimulationError::invalid(
+                "lower",
+                format!("should be below upper, got {} and {}", lower, upper),
+            ));
+        }
+
+        // the bounds must leave something to sample from
+
+        if normal.cdf(upper) - normal.cdf(lower) <= 0.0 {
+            return Err(SimulationError::invalid(
+                "lower",
+                "leaves no chance of any value between the bounds",
+            ));
+        }
+
+        Ok(TruncatedNormal {
+            normal,
+            lower,
+            upper,
+        })
+    }
+}
+
+impl TruncatedNormal {
+    /// The bounds in standard deviations from the mean, and the chance between them.
+    fn standardised(&self) -> (f64, f64, f64) {
+        let alpha = (self.lower - self.normal.mean) / self.normal.standard_deviation;
+        let beta = (self.upper - self.normal.mean) / self.normal.standard_deviation;
+
+        (
+            alpha,
+            beta,
+            standard_normal_cdf(beta) - standard_normal_cdf(alpha),
+        )
+    }
+}
+
+impl ContinuousDistribution for TruncatedNormal {
+    fn pdf(&self, x: f64) -> f64 {
+        if x < self.lower || x > self.upper {
+            return 0.0;
+        }
+
+        self.normal.pdf(x) / self.standardised().2
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        if x <= self.lower {
+            return 0.0;
+        }
+
+        if x >= self.upper {
+            return 1.0;
+        }
+
+        (self.normal.cdf(x) - self.normal.cdf(self.lower)) / self.standardised().2
+    }
+
+    fn quantile(&self, p: f64) -> f64 {
+        let (alpha, _, chance) = self.standardised();
+
+        let x = self
+            .normal
+            .quantile(standard_normal_cdf(alpha) + p * chance);
+
+        x.clamp(self.lower, self.upper)
+    }
+
+    fn mean(&self) -> f64 {
+        let (alpha, beta, chance) = self.standardised();
+
+        self.normal.mean
+            + self.normal.standard_deviation
+                * (standard_normal_pdf(alpha) - standard_normal_pdf(beta))
+                / chance
+    }
+
+    fn variance(&self) -> f64 {
+        let (alpha, beta, chance) = self.standardised();
+
+        // x * φ(x) goes to 0 at infinite bounds
+
+        let weighted = |x: f64| {
+            if x.is_finite() {
+                x * standard_normal_pdf(x)
+            } else {
+                0.0
+            }
+        };
+
+        let shift = (standard_normal_pdf(alpha) - standard_normal_pdf(beta)) / chance;
+
+        self.normal.variance() * (1.0 + (weighted(alpha) - weighted(beta)) / chance - shift * shift)
+    }
+}
+
+/// A distribution whose logarithm is normal, with mean `mu` and standard deviation `sigma`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogNormal {
+    mu: f64,
+    sigma: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl LogNormal {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(mu: f64, sigma: f64) -> Result<LogNormal, SimulationError> {
+        Ok(LogNormal {
+            mu: finite("mu", mu)?,
+            sigma: positive("sigma", sigma)?,
+        })
+    }
+}
+
+impl ContinuousDistribution for LogNormal {
+    fn pdf(&self, x: f64) -> f64 {
+        if x <= 0.0 {
+            return 0.0;
+        }
+
+        standard_normal_pdf((x.ln() - self.mu) / self.sigma) / (x * self.sigma)
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        if x <= 0.0 {
+            return 0.0;
+        }
+
+        standard_normal_cdf((x.ln() - self.mu) / self.sigma)
+    }
+
+    fn quantile(&self, p: f64) -> f64 {
+        (self.mu + self.sigma * standard_normal_quantile(p)).exp()
+    }
+
+    fn mean(&self) -> f64 {
+        (self.mu + self.sigma * self.sigma / 2.0).exp()
+    }
+
+    fn variance(&self) -> f64 {
+        ((self.sigma * self.sigma).exp() - 1.0) * (2.0 * self.mu + self.sigma * self.sigma).exp()
+    }
+}
+
+/// Survival times with a hazard that grows exponentially with time, `hazard * e^(growth * t)`.
+/// This is the classic shape of adult mortality.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gompertz {
+    hazard: f64, // at time 0
+    growth: f64, // of the hazard, per unit of time
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Gompertz {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(hazard: f64, growth: f64) -> Result<Gompertz, SimulationError> {
+        Ok(Gompertz {
+            hazard: positive("hazard", hazard)?,
+            growth: positive("growth", growth)?,
+        })
+    }
+}
+
+impl Gompertz {
+    fn survival(&self, t: f64) -> f64 {
+        (-self.hazard / self.growth * (self.growth * t).exp_m1()).exp()
+    }
+
+    /// The integral of `f` times the chance of surviving to `t`, for the moments. There is no
+    /// simple closed form, so this uses Simpson's rule up to where nobody is left.
+    fn integrate_survival(&self, f: impl Fn(f64) -> f64) -> f64 {
+        const STEPS: usize = 4000;
+
+        let end = ContinuousDistribution::quantile(self, 1.0 - 1e-12);
+        let step = end / STEPS as f64;
+
+        let value = |i: usize| {
+            let t = i as f64 * step;
+            f(t) * self.survival(t)
+        };
+
+        let inner: f64 = (1..STEPS)
+            .map(|i| if i % 2 == 1 { 4.0 } else { 2.0 } * value(i))
+            .sum();
+
+        (value(0) + inner + value(STEPS)) * step / 3.0
+    }
+}
+
+impl ContinuousDistribution for Gompertz {
+    fn pdf(&self, t: f64) -> f64 {
+        if t < 0.0 {
+            return 0.0;
+        }
+
+        self.hazard * (self.growth * t).exp() * self.survival(t)
+    }
+
+    fn cdf(&self, t: f64) -> f64 {
+        if t <= 0.0 {
+            return 0.0;
+        }
+
+        1.0 - self.survival(t)
+    }
+
+    fn quantile(&self, p: f64) -> f64 {
+        (-self.growth / self.hazard * (-p).ln_1p()).ln_1p() / self.growth
+    }
+
+    fn mean(&self) -> f64 {
+        self.integrate_survival(|_| 1.0)
+    }
+
+    fn variance(&self) -> f64 {
+        let mean = ContinuousDistribution::mean(self);
+
+        self.integrate_survival(|t| 2.0 * t) - mean * mean
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weibull {
+    shape: f64, // above 1 the hazard grows with time, below 1 it shrinks
+    scale: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Weibull {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(shape: f64, scale: f64) -> Result<Weibull, SimulationError> {
+        Ok(Weibull {
+            shape: positive("shape", shape)?,
+            scale: positive("scale", scale)?,
+        })
+    }
+}
+
+impl ContinuousDistribution for Weibull {
+    fn pdf(&self, x: f64) -> f64 {
+        if x < 0.0 {
+            return 0.0;
+        }
+
+        let z = x / self.scale;
+
+        self.shape / self.scale * z.powf(self.shape - 1.0) * (-z.powf(self.shape)).exp()
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        if x <= 0.0 {
+            return 0.0;
+        }
+
+        -(-(x / self.scale).powf(self.shape)).exp_m1()
+    }
+
+    fn quantile(&self, p: f64) -> f64 {
+        self.scale * (-(-p).ln_1p()).powf(1.0 / self.shape)
+    }
+
+    fn mean(&self) -> f64 {
+        self.scale * ln_gamma(1.0 + 1.0 / self.shape).exp()
+    }
+
+    fn variance(&self) -> f64 {
+        let first = ln_gamma(1.0 + 1.0 / self.shape).exp();
+        let second = ln_gamma(1.0 + 2.0 / self.shape).exp();
+
+        self.scale * self.scale * (second - first * first)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gamma {
+    shape: f64,
+    scale: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Gamma {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(shape: f64, scale: f64) -> Result<Gamma, SimulationError> {
+        Ok(Gamma {
+            shape: positive("shape", shape)?,
+            scale: positive("scale", scale)?,
+        })
+    }
+}
+
+impl ContinuousDistribution for Gamma {
+    fn pdf(&self, x: f64) -> f64 {
+        if x < 0.0 {
+            return 0.0;
+        }
+
+        if x == 0.0 {
+            // the density at 0 blows up below a shape of 1, and is 0 above it
+
+            return match self.shape.partial_cmp(&1.0) {
+                Some(std::cmp::Ordering::Less) => f64::INFINITY,
+                Some(std::cmp::Ordering::Equal) => 1.0 / self.scale,
+                _ => 0.0,
+            };
+        }
+
+        let z = x / self.scale;
+
+        ((self.shape - 1.0) * z.ln() - z - ln_gamma(self.shape)).exp() / self.scale
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        regularized_gamma(self.shape, x / self.scale)
+    }
+
+    fn quantile(&self, p: f64) -> f64 {
+        let spread = ContinuousDistribution::variance(self).sqrt();
+
+        invert(
+            |x| self.cdf(x),
+            p,
+            0.0,
+            self.shape * self.scale + 10.0 * spread,
+        )
+    }
+
+    fn mean(&self) -> f64 {
+        self.shape * self.scale
+    }
+
+    fn variance(&self) -> f64 {
+        self.shape * self.scale * self.scale
+    }
+
+    /// Marsaglia and Tsang's method, which is much faster than inverting the CDF.
+    fn sample(&self, rng: &mut dyn RngCore) -> f64 {
+        // below a shape of 1, sample with the shape plus 1 and scale down
+
+        if self.shape < 1.0 {
+            let boosted = Gamma {
+                shape: self.shape + 1.0,
+                scale: self.scale,
+            };
+
+            let u: f64 = rng.sample(Open01);
+
+            return boosted.sample(rng) * u.powf(1.0 / self.shape);
+        }
+
+        let d = self.shape - 1.0 / 3.0;
+        let c = 1.0 / (9.0 * d).sqrt();
+
+        loop {
+            let x = standard_normal_quantile(rng.sample(Open01));
+            let v = (1.0 + c * x).powi(3);
+
+            if v <= 0.0 {
+                continue;
+            }
+
+            let u: f64 = rng.sample(Open01);
+
+            if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+                return d * v * self.scale;
+            }
+        }
+    }
+}
+
+/// Shares between 0 and 1.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Beta {
+    alpha: f64,
+    beta: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Beta {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(alpha: f64, beta: f64) -> Result<Beta, SimulationError> {
+        Ok(Beta {
+            alpha: positive("alpha", alpha)?,
+            beta: positive("beta", beta)?,
+        })
+    }
+}
+
+impl ContinuousDistribution for Beta {
+    fn pdf(&self, x: f64) -> f64 {
+        if !(0.0..=1.0).contains(&x) {
+            return 0.0;
+        }
+
+        let log_beta =
+            ln_gamma(self.alpha) + ln_gamma(self.beta) - ln_gamma(self.alpha + self.beta);
+
+        ((self.alpha - 1.0) * x.ln() + (self.beta - 1.0) * (1.0 - x).ln() - log_beta).exp()
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        regularized_beta(self.alpha, self.beta, x)
+    }
+
+    fn quantile(&self, p: f64) -> f64 {
+        invert(|x| self.cdf(x), p, 0.0, 1.0)
+    }
+
+    fn mean(&self) -> f64 {
+        self.alpha / (self.alpha + self.beta)
+    }
+
+    fn variance(&self) -> f64 {
+        let total = self.alpha + self.beta;
+
+        self.alpha * self.beta / (total * total * (total + 1.0))
+    }
+
+    /// A share of two gamma samples, which is faster than inverting the CDF.
+    fn sample(&self, rng: &mut dyn RngCore) -> f64 {
+        let x = Gamma {
+            shape: self.alpha,
+            scale: 1.0,
+        }
+        .sample(rng);
+        let y = Gamma {
+            shape: self.beta,
+            scale: 1.0,
+        }
+        .sample(rng);
+
+        x / (x + y)
+    }
+}
+
+/// Poisson rates above this find their quantiles from a guess, instead of adding up the chances
+/// from 0.
+const POISSON_WALK_LIMIT: f64 = 30.0;
+
+/// The number of events in a period, when they happen independently at an average `rate`. The
+/// `cdf`, and so the quantiles and samples, are accurate for rates up to about 100000.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Poisson {
+    rate: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Poisson {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(rate: f64) -> Result<Poisson, SimulationError> {
+        if !(rate.is_finite() && rate >= 0.0) {
+            return Err(SimulationError::invalid(
+                "rate",
+                format!("should be a number of at least 0, got {}", rate),
+            ));
+        }
+
+        Ok(Poisson { rate })
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn pmf(&self, k: u32) -> f64 {
+        DiscreteDistribution::pmf(self, k as u64)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn cdf(&self, k: u32) -> f64 {
+        DiscreteDistribution::cdf(self, k as u64)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn quantile(&self, p: f64) -> u32 {
+        DiscreteDistribution::quantile(self, p).min(u32::MAX as u64) as u32
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn mean(&self) -> f64 {
+        self.rate
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn variance(&self) -> f64 {
+        self.rate
+    }
+
+    /// `count` samples drawn with `seed`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn samples(&self, count: u32, seed: u64) -> Vec<u32> {
+        let mut rng = seeded(seed);
+
+        (0..count)
+            .map(|_| DiscreteDistribution::sample(self, &mut rng).min(u32::MAX as u64) as u32)
+            .collect()
+    }
+}
+
+impl Poisson {
+    /// The quantile for large rates. The guess is the normal approximation with a correction for
+    /// skew (Cornish-Fisher), which is within a few counts of the answer, and the `cdf` is only
+    /// worked out once, at the guess.
+    fn quantile_from_guess(&self, p: f64) -> u64 {
+        let z = standard_normal_quantile(p);
+        let guess = self.rate + z * self.rate.sqrt() + (z * z - 1.0) / 6.0;
+
+        let mut k = guess.round().max(0.0) as u64;
+        let mut total = DiscreteDistribution::cdf(self, k);
+
+        if total >= p {
+            // step down while the count below still reaches p
+
+            while k > 0 {
+                let below = total - DiscreteDistribution::pmf(self, k);
+
+                if below < p {
+                    break;
+                }
+
+                total = below;
+                k -= 1;
+            }
+        } else {
+            while total < p {
+                k += 1;
+
+                let chance = DiscreteDistribution::pmf(self, k);
+
+                if chance == 0.0 && k as f64 > self.rate {
+                    break;
+                }
+
+                total += chance;
+            }
+        }
+
+        k
+    }
+}
+
+impl DiscreteDistribution for Poisson {
+    fn pmf(&self, k: u64) -> f64 {
+        if self.rate == 0.0 {
+            return if k == 0 { 1.0 } else { 0.0 };
+        }
+
+        (k as f64 * self.rate.ln() - self.rate - ln_gamma(k as f64 + 1.0)).exp()
+    }
+
+    fn cdf(&self, k: u64) -> f64 {
+        if self.rate == 0.0 {
+            return 1.0;
+        }
+
+        1.0 - regularized_gamma(k as f64 + 1.0, self.rate)
+    }
+
+    /// Adds up the chances from 0 for small rates. Larger rates start from a guess with the
+    /// normal approximation and step from there to the exact count, which takes time in
+    /// proportion to the square root of the rate instead of the rate.
+    fn quantile(&self, p: f64) -> u64 {
+        if p >= 1.0 {
+            return u64::MAX;
+        }
+
+        if self.rate > POISSON_WALK_LIMIT {
+            return self.quantile_from_guess(p);
+        }
+
+        let mut k = 0;
+        let mut total = DiscreteDistribution::pmf(self, 0);
+
+        while total < p {
+            k += 1;
+
+            let chance = DiscreteDistribution::pmf(self, k);
+
+            // past the mean, a chance that adds nothing means we've run out of precision
+
+            if chance == 0.0 && k as f64 > self.rate {
+                break;
+            }
+
+            total += chance;
+        }
+
+        k
+    }
+
+    fn mean(&self) -> f64 {
+        self.rate
+    }
+
+    fn variance(&self) -> f64 {
+        self.rate
+    }
+}
+
+fn standard_normal_pdf(x: f64) -> f64 {
+    (-0.5 * x * x).exp() / (2.0 * PI).sqrt()
+}
+
+fn standard_normal_cdf(x: f64) -> f64 {
+    // 1/2 * erfc(-x / sqrt(2)), which keeps its precision far into the lower tail
+
+    0.5 * errorfunctions::RealErrorFunctions::erfc(-x / SQRT_2)
+}
+
+/// The inverse of the standard normal CDF, with Acklam's rational approximation polished by a
+/// step of Halley's method, which makes it accurate to about 1e-15.
+fn standard_normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    const LOW: f64 = 0.02425;
+
+    if p.is_nan() || !(0.0..=1.0).contains(&p) {
+        return f64::NAN;
+    }
+
+    if p == 0.0 {
+        return f64::NEG_INFINITY;
+    }
+
+    if p == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    let x = if p < LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p <= 1.0 - LOW {
+        let q = p - 0.5;
+        let r = q * q;
+
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    };
+
+    let error = standard_normal_cdf(x) - p;
+    let u = error * (2.0 * PI).sqrt() * (x * x / 2.0).exp();
+
+    x - u / (1.0 + x * u / 2.0)
+}
+
+/// The logarithm of the gamma function, with Lanczos' approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        // the reflection formula
+
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+const TINY: f64 = 1e-300;
+const PRECISION: f64 = 1e-15;
+const MAX_ITERATIONS: usize = 1000;
+
+/// The regularized lower incomplete gamma function P(a, x), with a series below `a + 1` and a
+/// continued fraction above (as in Numerical Recipes).
+fn regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+
+    let front = (-x + a * x.ln() - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+
+        for n in 1..MAX_ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+
+            if term.abs() < sum.abs() * PRECISION {
+                break;
+            }
+        }
+
+        (sum * front).min(1.0)
+    } else {
+        // Lentz's method for the upper function Q(a, x)
+
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / TINY;
+        let mut d = 1.0 / b;
+        let mut h = d;
+
+        for i in 1..MAX_ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+
+            d = an * d + b;
+            d = if d.abs() < TINY { TINY } else { d };
+            c = b + an / c;
+            c = if c.abs() < TINY { TINY } else { c };
+            d = 1.0 / d;
+
+            let delta = d * c;
+            h *= delta;
+
+            if (delta - 1.0).abs() < PRECISION {
+                break;
+            }
+        }
+
+        (1.0 - front * h).max(0.0)
+    }
+}
+
+/// The regularized incomplete beta function I_x(a, b), with a continued fraction (as in
+/// Numerical Recipes).
+fn regularized_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+
+    // the continued fraction converges quickly on this side, so use symmetry for the other
+
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    let clamp = |value: f64| if value.abs() < TINY { TINY } else { value };
+
+    let mut c = 1.0;
+    let mut d = 1.0 / clamp(1.0 - (a + b) * x / (a + 1.0));
+    let mut h = d;
+
+    for m in 1..MAX_ITERATIONS {
+        let m = m as f64;
+
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / clamp(1.0 + even * d);
+        c = clamp(1.0 + even / c);
+        h *= d * c;
+
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / clamp(1.0 + odd * d);
+        c = clamp(1.0 + odd / c);
+
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < PRECISION {
+            break;
+        }
+    }
+
+    h
+}
+
+/// Finds where an increasing `cdf` reaches `p` by bisection, widening `upper` until it is
+/// passed.
+fn invert(cdf: impl Fn(f64) -> f64, p: f64, lower: f64, upper: f64) -> f64 {
+    if p.is_nan() || !(0.0..=1.0).contains(&p) {
+        return f64::NAN;
+    }
+
+    let (mut lower, mut upper) = (lower, upper);
+
+    while cdf(upper) < p && upper.is_finite() {
+        upper *= 2.0;
+    }
+
+    for _ in 0..200 {
+        let middle = (lower + upper) / 2.0;
+
+        if cdf(middle) < p {
+            lower = middle;
+        } else {
+            upper = middle;
+        }
+
+        if upper - lower <= PRECISION * upper.abs().max(1.0) {
+            break;
+        }
+    }
+
+    (lower + upper) / 2.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn test_known_values() {
+        let normal = NormalDistribution::new(0.0, 1.0);
+
+        assert!(close(normal.quantile(0.975), 1.959_963_984_540_054, 1e-12));
+        assert!(close(normal.cdf(-3.0), 0.001_349_898_031_630_094_6, 1e-12));
+
+        // Γ(5) = 24
+
+        assert!(close(ln_gamma(5.0).exp(), 24.0, 1e-12));
+
+        // an exponential distribution is a gamma with shape 1, and a Weibull with shape 1
+
+        let gamma = Gamma::new(1.0, 2.0).unwrap();
+        let weibull = Weibull::new(1.0, 2.0).unwrap();
+
+        assert!(close(gamma.cdf(3.0), 1.0 - (-1.5f64).exp(), 1e-12));
+        assert!(close(weibull.cdf(3.0), 1.0 - (-1.5f64).exp(), 1e-12));
+
+        let beta = Beta::new(2.0, 3.0).unwrap();
+
+        // I_0.5(2, 3) = 11/16
+
+        assert!(close(beta.cdf(0.5), 11.0 / 16.0, 1e-12));
+
+        let poisson = Poisson::new(3.0).unwrap();
+
+        assert!(close(poisson.cdf(2), 8.5 * (-3.0f64).exp(), 1e-12));
+        assert_eq!(poisson.quantile(0.5), 3);
+    }
+
+    #[test]
+    fn test_quantiles_invert_cdfs() {
+        let distributions: Vec<Box<dyn ContinuousDistribution>> = vec![
+            Box::new(NormalDistribution::new(1.0, 2.0)),
+            Box::new(TruncatedNormal::new(0.5, 0.3, 0.0, f64::INFINITY).unwrap()),
+            Box::new(LogNormal::new(0.0, 0.5).unwrap()),
+            Box::new(Gompertz::new(0.0001, 0.09).unwrap()),
+            Box::new(Weibull::new(1.5, 10.0).unwrap()),
+            Box::new(Gamma::new(0.7, 3.0).unwrap()),
+            Box::new(Beta::new(2.0, 5.0).unwrap()),
+        ];
+
+        for distribution in &distributions {
+            for p in [0.01, 0.25, 0.5, 0.75, 0.99] {
+                let x = distribution.quantile(p);
+
+                assert!(close(distribution.cdf(x), p, 1e-9), "{:?}", (p, x));
+            }
+        }
+    }
+
+    #[test]
+    fn test_samples_match_moments() {
+        let distributions: Vec<Box<dyn ContinuousDistribution>> = vec![
+            Box::new(TruncatedNormal::new(0.2, 0.3, 0.0, 1.0).unwrap()),
+            Box::new(LogNormal::new(0.0, 0.5).unwrap()),
+            Box::new(Gompertz::new(0.0001, 0.09).unwrap()),
+            Box::new(Weibull::new(1.5, 10.0).unwrap()),
+            Box::new(Gamma::new(0.7, 3.0).unwrap()),
+            Box::new(Gamma::new(4.0, 0.5).unwrap()),
+            Box::new(Beta::new(2.0, 5.0).unwrap()),
+        ];
+
+        let mut rng = seeded(20);
+
+        for distribution in &distributions {
+            let samples: Vec<f64> = (0..50_000).map(|_| distribution.sample(&mut rng)).collect();
+
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            let variance =
+                samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+
+            // within a few standard errors
+
+            let error = (distribution.variance() / samples.len() as f64).sqrt();
+
+            assert!((mean - distribution.mean()).abs() < 5.0 * error, "{}", mean);
+            assert!(
+                close(variance, distribution.variance(), 0.05),
+                "{}",
+                variance
+            );
+        }
+
+        let poisson = Poisson::new(4.5).unwrap();
+        let mean = poisson.samples(50_000, 20).iter().sum::<u32>() as f64 / 50_000.0;
+
+        assert!((mean - 4.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_large_poisson() {
+        // large rates start from a guess, and still land on the smallest count that reaches p
+
+        for rate in [31.0, 250.0, 5000.0, 1e5] {
+            let poisson = Poisson::new(rate).unwrap();
+
+            for p in [1e-9, 0.01, 0.3, 0.5, 0.9, 0.999_999] {
+                let k = poisson.quantile(p);
+
+                assert!(poisson.cdf(k) >= p - 1e-9, "{:?}", (rate, p, k));
+                assert!(
+                    k == 0 || poisson.cdf(k - 1) < p + 1e-9,
+                    "{:?}",
+                    (rate, p, k)
+                );
+            }
+        }
+
+        let poisson = Poisson::new(1e6).unwrap();
+        let mean = poisson
+            .samples(1000, 20)
+            .iter()
+            .map(|&k| k as f64)
+            .sum::<f64>()
+            / 1000.0;
+
+        assert!((mean - 1e6).abs() < 100.0);
+    }
+}
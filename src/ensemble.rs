@@ -0,0 +1,239 @@
+//! Monte Carlo ensembles for Beanreadings. A single run of the simulation is one noisy
+//! trajectory, so we run the same settings many times with independent seeds and summarise every
+//! tracked series year by year. This is what the fan charts in the UI are drawn from.
+
+use crate::error::SimulationError;
+use crate::rng::derive_seed;
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Per-year summary of one series across every replicate of an ensemble.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeriesSummary {
+    pub name: String,
+    pub mean: Vec<f64>,
+    pub median: Vec<f64>,
+    pub std_dev: Vec<f64>,
+    pub bands: Vec<Vec<f64>>, // one series per requested percentile, in the same order
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnsembleResult {
+    replicates: u32,
+    percentiles: Vec<f64>,
+    series: Vec<SeriesSummary>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl EnsembleResult {
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_replicates(&self) -> u32 {
+        self.replicates
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_percentiles(&self) -> Vec<f64> {
+        self.percentiles.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_series_names(&self) -> Vec<String> {
+        self.series
+            .iter()
+            .map(|series| series.name.clone())
+            .collect()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_mean(&self, name: &str) -> Vec<f64> {
+        self.series(name)
+            .map(|series| series.mean.clone())
+            .unwrap_or_default()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_median(&self, name: &str) -> Vec<f64> {
+        self.series(name)
+            .map(|series| series.median.clone())
+            .unwrap_or_default()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_std_dev(&self, name: &str) -> Vec<f64> {
+        self.series(name)
+            .map(|series| series.std_dev.clone())
+            .unwrap_or_default()
+    }
+
+    /// The band for one of the percentiles the ensemble was run with. Percentiles that were not
+    /// requested give an empty series.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_percentile(&self, name: &str, percentile: f64) -> Vec<f64> {
+        let band = self.percentiles.iter().position(|&p| p == percentile);
+
+        match (self.series(name), band) {
+            (Some(series), Some(band)) => series.bands[band].clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl EnsembleResult {
+    pub fn series(&self, name: &str) -> Option<&SeriesSummary> {
+        self.series.iter().find(|series| series.name == name)
+    }
+
+    pub fn all_series(&self) -> &[SeriesSummary] {
+        &self.series
+    }
+
+    /// Summarises the results of every replicate. All of the results must come from the same
+    /// settings, so that every series has the same names and lengths.
+    pub fn from_results(results: &[SimulationResult], percentiles: &[f64]) -> EnsembleResult {
+        let runs: Vec<Vec<(String, Vec<f64>)>> =
+            results.iter().map(|result| result.series()).collect();
+
+        let names: Vec<String> = runs
+            .first()
+            .map(|run| run.iter().map(|(name, _)| name.clone()).collect())
+            .unwrap_or_default();
+
+        let series = names
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let values: Vec<&Vec<f64>> = runs.iter().map(|run| &run[index].1).collect();
+
+                summarise(name, &values, percentiles)
+            })
+            .collect();
+
+        EnsembleResult {
+            replicates: results.len() as u32,
+            percentiles: percentiles.to_vec(),
+            series,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Simulation {
+    /// Runs the simulation `replicates` times with independent seeds derived from
+    /// `settings.seed`, and summarises every series. `percentiles` go from 0 to 100, e.g.
+    /// `[5, 50, 95]` for a 90% band around the median.
+    pub fn ensemble(&self, replicates: u32, percentiles: Vec<f64>) -> EnsembleResult {
+        let results: Vec<SimulationResult> = (0..replicates)
+            .map(|replicate| {
+                let mut settings = self.settings.clone();
+                settings.seed = derive_seed(self.settings.seed, replicate as u64);
+
+                self.with_settings(settings).long()
+            })
+            .collect();
+
+        EnsembleResult::from_results(&results, &percentiles)
+    }
+
+    /// Checks the settings, then runs an ensemble.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn try_ensemble(
+        &self,
+        replicates: u32,
+        percentiles: Vec<f64>,
+    ) -> Result<EnsembleResult, SimulationError> {
+        self.settings.validate()?;
+
+        Ok(self.ensemble(replicates, percentiles))
+    }
+}
+
+fn summarise(name: String, values: &[&Vec<f64>], percentiles: &[f64]) -> SeriesSummary {
+    let years = values.iter().map(|series| series.len()).min().unwrap_or(0);
+
+    let mut summary = SeriesSummary {
+        name,
+        mean: Vec::with_capacity(years),
+        median: Vec::with_capacity(years),
+        std_dev: Vec::with_capacity(years),
+        bands: vec![Vec::with_capacity(years); percentiles.len()],
+    };
+
+    for year in 0..years {
+        let mut sample: Vec<f64> = values.iter().map(|series| series[year]).collect();
+        sample.sort_by(|a, b| a.total_cmp(b));
+
+        let n = sample.len() as f64;
+        let mean = sample.iter().sum::<f64>() / n;
+
+        let variance = if sample.len() > 1 {
+            sample.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)
+        } else {
+            0.0
+        };
+
+        summary.mean.push(mean);
+        summary.median.push(percentile(&sample, 50.0));
+        summary.std_dev.push(variance.sqrt());
+
+        for (band, &p) in summary.bands.iter_mut().zip(percentiles) {
+            band.push(percentile(&sample, p));
+        }
+    }
+
+    summary
+}
+
+/// Percentile of a sorted sample, interpolating linearly between the two closest values.
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f64;
+
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let sample = [1.0, 2.0, 3.0, 4.0, 5.0];
+
+        assert_eq!(percentile(&sample, 0.0), 1.0);
+        assert_eq!(percentile(&sample, 50.0), 3.0);
+        assert_eq!(percentile(&sample, 100.0), 5.0);
+        assert_eq!(percentile(&sample, 12.5), 1.5);
+    }
+
+    #[test]
+    fn test_ensemble_bands() {
+        let mut settings = Settings::new();
+        settings.set_seed(11);
+        settings.set_population(200);
+
+        let ensemble = Simulation::new(settings.clone()).ensemble(8, vec![5.0, 95.0]);
+
+        assert_eq!(ensemble.get_replicates(), 8);
+
+        let low = ensemble.get_percentile("population", 5.0);
+        let median = ensemble.get_median("population");
+        let high = ensemble.get_percentile("population", 95.0);
+
+        assert_eq!(median.len(), settings.years as usize);
+
+        for year in 0..median.len() {
+            assert!(low[year] <= median[year] && median[year] <= high[year]);
+        }
+
+        assert!(ensemble.get_percentile("population", 50.0).is_empty());
+    }
+}
@@ -0,0 +1,55 @@
+//! Errors for Beanreadings. Settings are checked before a simulation starts, so that bad input is
+//! reported with the setting that caused it instead of crashing the simulation halfway through.
+
+use crate::types::ErrorCode;
+use std::fmt;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Something wrong with the settings of a simulation, and which setting it was.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationError {
+    pub field: String, // the name of the setting, as in `Settings`
+
+    pub reason: String,
+
+    pub code: ErrorCode,
+}
+
+impl SimulationError {
+    pub fn new(field: impl Into<String>, reason: impl Into<String>, code: ErrorCode) -> Self {
+        SimulationError {
+            field: field.into(),
+            reason: reason.into(),
+            code,
+        }
+    }
+
+    /// A setting with a value that doesn't make sense.
+    pub fn invalid(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        SimulationError::new(field, reason, ErrorCode::InvalidParameters)
+    }
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} {}",
+            String::from(self.code),
+            self.field,
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+// thrown as a JavaScript Error, so the message shows up in the console and in `error.message`
+
+#[cfg(feature = "wasm")]
+impl From<SimulationError> for JsValue {
+    fn from(error: SimulationError) -> JsValue {
+        JsError::new(&error.to_string()).into()
+    }
+}
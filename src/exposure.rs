@@ -0,0 +1,325 @@
+//! Doses for Beanreadings. A bean that smokes a cigarette a week doesn't have the same risk as a
+//! bean that smokes two packs a day, so smoking, drinking and hard drugs come with an amount.
+//!
+//! Every bean draws how much it uses of each of them when it is created, from a log-normal
+//! distribution with the `median` and `spread` in the settings, cut off at `maximum`. Only the
+//! habits it has are used: the amounts in `Factors::exposures` are 0 for everything else, and
+//! go back to 0 when the bean quits. A bean that picks a habit up later, or relapses, uses the
+//! amount it was created with. Smoking also builds up pack-years (a pack of 20 a day for a year is
+//! one pack-year), which stay after quitting, and beans in the starting population are taken to
+//! have smoked since they were adults.
+//!
+//! The relative risks in the hazard table are for the `reference` dose. For other doses, the
+//! relative risk is `relative_risk ^ ((dose / reference) ^ shape)`, so a shape of 1 is a
+//! log-linear dose-response curve and smaller shapes level off at high doses. Smoking is measured
+//! in pack-years, drinking in drinks per week and hard drugs in days of use per month. Beans
+//! without a dose, like ones from `Factors::new`, get the relative risk as it is in the table.
+
+use crate::distributions::{ContinuousDistribution, LogNormal};
+use crate::factors::Habit;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Cigarettes in a pack, for working out pack-years.
+pub const CIGARETTES_PER_PACK: f64 = 20.0;
+
+/// Beans in the starting population are taken to have smoked since this age, or for a year if
+/// they are younger.
+const SMOKING_SINCE: u32 = 18;
+
+/// How much of a habit a bean uses.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Exposures {
+    pub cigarettes_per_day: f64,
+
+    pub pack_years: f64, // smoked so far, it stays after quitting
+
+    pub drinks_per_week: f64,
+
+    pub drug_days_per_month: f64, // days a month with hard drug use
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Exposures {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        cigarettes_per_day: f64,
+        pack_years: f64,
+        drinks_per_week: f64,
+        drug_days_per_month: f64,
+    ) -> Exposures {
+        Exposures {
+            cigarettes_per_day,
+            pack_years,
+            drinks_per_week,
+            drug_days_per_month,
+        }
+    }
+
+    /// The pack-years of a bean of this age that has smoked since it was an adult.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn pack_years_at(&self, age: u32) -> f64 {
+        let years = age.saturating_sub(SMOKING_SINCE).max(1);
+
+        self.cigarettes_per_day / CIGARETTES_PER_PACK * years as f64
+    }
+
+    /// How much of a habit is used now: cigarettes a day, drinks a week or days of hard drug use a
+    /// month. Habits without a dose have none.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn amount(&self, habit: Habit) -> f64 {
+        match habit {
+            Habit::SmokesCigarettes => self.cigarettes_per_day,
+            Habit::Alcoholism => self.drinks_per_week,
+            Habit::ConsumesHardDrugs => self.drug_days_per_month,
+            _ => 0.0,
+        }
+    }
+
+    /// Sets how much of a habit is used now. Habits without a dose are left alone.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_amount(&mut self, habit: Habit, amount: f64) {
+        match habit {
+            Habit::SmokesCigarettes => self.cigarettes_per_day = amount,
+            Habit::Alcoholism => self.drinks_per_week = amount,
+            Habit::ConsumesHardDrugs => self.drug_days_per_month = amount,
+            _ => {}
+        }
+    }
+
+    /// Adds a year of smoking to the pack-years.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn smoke_for_a_year(&mut self) {
+        self.pack_years += self.cigarettes_per_day / CIGARETTES_PER_PACK;
+    }
+}
+
+impl Exposures {
+    /// The dose the dose-response curve of a habit uses, or `None` for habits without one. Smokers
+    /// count as having smoked for at least a year.
+    pub fn dose(&self, habit: Habit) -> Option<f64> {
+        match habit {
+            Habit::SmokesCigarettes => Some(
+                self.pack_years
+                    .max(self.cigarettes_per_day / CIGARETTES_PER_PACK),
+            ),
+            Habit::Alcoholism => Some(self.drinks_per_week),
+            Habit::ConsumesHardDrugs => Some(self.drug_days_per_month),
+            _ => None,
+        }
+    }
+}
+
+/// The distribution of a dose among users, and its dose-response curve.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dose {
+    pub median: f64,
+
+    pub spread: f64, // standard deviation of the log of the dose
+
+    pub maximum: f64,
+
+    pub reference: f64, // the dose the hazard table's relative risks are for
+
+    pub shape: f64, // 1 is log-linear, below 1 levels off at high doses
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Dose {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(median: f64, spread: f64, maximum: f64, reference: f64, shape: f64) -> Dose {
+        Dose {
+            median,
+            spread,
+            maximum,
+            reference,
+            shape,
+        }
+    }
+
+    /// How many times the table's extra risk (on a log scale) a dose carries, 1 at the reference
+    /// dose. Beans without a dose get 1.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn scale(&self, dose: f64) -> f64 {
+        if dose > 0.0 {
+            (dose / self.reference).powf(self.shape)
+        } else {
+            1.0
+        }
+    }
+}
+
+impl Dose {
+    /// Draws a dose for a bean.
+    pub fn sample(&self, rng: &mut dyn RngCore) -> f64 {
+        LogNormal::new(self.median.ln(), self.spread)
+            .map(|distribution| distribution.sample(rng).min(self.maximum))
+            .unwrap_or(self.median) // only when the settings are invalid, which validation catches
+    }
+}
+
+/// The doses of smoking, drinking and hard drugs. The defaults are round numbers: a median smoker
+/// has half a pack a day, a median drinker ten drinks a week and a median hard drug user four
+/// days a month, and the hazard table's risks are for twenty pack-years, fourteen drinks a week
+/// and eight days a month.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Doses {
+    pub cigarettes: Dose, // drawn in cigarettes a day, with a reference in pack-years
+
+    pub alcohol: Dose, // drinks a week
+
+    pub hard_drugs: Dose, // days of use a month
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Doses {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> Doses {
+        Doses {
+            cigarettes: Dose::new(10.0, 0.6, 60.0, 20.0, 0.8),
+            alcohol: Dose::new(10.0, 0.8, 100.0, 14.0, 1.0),
+            hard_drugs: Dose::new(4.0, 1.0, 30.0, 8.0, 0.7),
+        }
+    }
+}
+
+impl Doses {
+    /// Draws how much of each habit a new bean would use, without any pack-years yet.
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Exposures {
+        Exposures {
+            cigarettes_per_day: self.cigarettes.sample(rng),
+            pack_years: 0.0,
+            drinks_per_week: self.alcohol.sample(rng),
+            drug_days_per_month: self.hard_drugs.sample(rng),
+        }
+    }
+
+    /// The dose-response curve of a habit, if it has one.
+    pub fn get(&self, habit: Habit) -> Option<&Dose> {
+        match habit {
+            Habit::SmokesCigarettes => Some(&self.cigarettes),
+            Habit::Alcoholism => Some(&self.alcohol),
+            Habit::ConsumesHardDrugs => Some(&self.hard_drugs),
+            _ => None,
+        }
+    }
+
+    /// How many times the table's extra risk of a habit a bean's dose carries, see `Dose::scale`.
+    pub fn scale(&self, habit: Habit, exposures: &Exposures) -> f64 {
+        match (self.get(habit), exposures.dose(habit)) {
+            (Some(dose), Some(amount)) => dose.scale(amount),
+            _ => 1.0,
+        }
+    }
+
+    /// Every number in the doses by name, for validation.
+    pub fn fields(&self) -> Vec<(String, f64)> {
+        [
+            ("cigarettes", self.cigarettes),
+            ("alcohol", self.alcohol),
+            ("hard_drugs", self.hard_drugs),
+        ]
+        .into_iter()
+        .flat_map(|(name, dose)| {
+            [
+                ("median", dose.median),
+                ("spread", dose.spread),
+                ("maximum", dose.maximum),
+                ("reference", dose.reference),
+                ("shape", dose.shape),
+            ]
+            .map(|(field, value)| (format!("doses.{}.{}", name, field), value))
+        })
+        .collect()
+    }
+}
+
+impl Default for Doses {
+    fn default() -> Self {
+        Doses::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bean::{Bean, Sex};
+    use crate::rng::seeded;
+    use crate::types::Settings;
+
+    #[test]
+    fn test_doses() {
+        let doses = Doses::new();
+        let mut rng = seeded(23);
+
+        let mut drinks: Vec<f64> = (0..10001)
+            .map(|_| doses.sample(&mut rng).drinks_per_week)
+            .collect();
+        drinks.sort_by(f64::total_cmp);
+
+        assert!((drinks[5000] - 10.0).abs() < 0.5);
+        assert!(drinks[10000] <= 100.0);
+
+        // twice the reference dose doubles the log of the relative risk with a log-linear curve
+
+        assert_eq!(doses.alcohol.scale(28.0), 2.0);
+        assert_eq!(doses.alcohol.scale(0.0), 1.0);
+        assert!(doses.cigarettes.scale(40.0) < 2.0);
+
+        let mut exposures = Exposures::new(20.0, 0.0, 0.0, 0.0);
+
+        assert_eq!(exposures.pack_years_at(48), 30.0);
+        assert_eq!(exposures.dose(Habit::SmokesCigarettes), Some(1.0));
+
+        exposures.smoke_for_a_year();
+        exposures.smoke_for_a_year();
+
+        assert_eq!(exposures.dose(Habit::SmokesCigarettes), Some(2.0));
+        assert_eq!(doses.scale(Habit::SmokesWeed, &exposures), 1.0);
+    }
+
+    #[test]
+    fn test_amounts() {
+        let mut settings = Settings::default();
+        settings.set_seed(23);
+
+        let mut rng = seeded(23);
+
+        // only the habits a bean has are used, the rest are 0
+
+        for _ in 0..1000 {
+            let factors = Bean::with_age(&settings, 40, Sex::Male, &mut rng).get_factors();
+
+            for habit in [
+                Habit::SmokesCigarettes,
+                Habit::Alcoholism,
+                Habit::ConsumesHardDrugs,
+            ] {
+                assert_eq!(factors.has(habit), factors.exposures.amount(habit) > 0.0);
+            }
+
+            assert_eq!(
+                factors.smokes_cigarettes,
+                factors.exposures.pack_years > 0.0
+            );
+        }
+
+        // quitting stops the use, but keeps the pack-years
+
+        let mut factors = Bean::with_age(&settings, 40, Sex::Male, &mut rng).get_factors();
+        factors.set(Habit::SmokesCigarettes, true);
+        factors.exposures = Exposures::new(20.0, 5.0, 0.0, 0.0);
+
+        factors.set(Habit::SmokesCigarettes, false);
+
+        assert_eq!(factors.exposures, Exposures::new(0.0, 5.0, 0.0, 0.0));
+    }
+}
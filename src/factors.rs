@@ -2,19 +2,24 @@
 //! upon creation of a bean, and will be used to determine whether a bean dies of a certain death or
 //! reproduces.
 
+use crate::bean::Sex;
+use crate::exposure::Exposures;
+use crate::types::Settings;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
 /// Factor trait, which contains a name and a value. The name is the name of the factor, and the
 /// value is the value of the factor. The value is a floating point number between 0 and 1, and
 /// represents the likelihood of the bean dying of a certain death.
-
 pub trait Factor {
     fn get_name(&self) -> String;
     fn get_value(&self) -> f64;
 }
 
-#[wasm_bindgen]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Sugar {
     pub value: f64,
 }
@@ -29,8 +34,9 @@ impl Factor for Sugar {
     }
 }
 
-#[wasm_bindgen]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Salt {
     pub value: f64,
 }
@@ -45,8 +51,9 @@ impl Factor for Salt {
     }
 }
 
-#[wasm_bindgen]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Fat {
     pub value: f64,
 }
@@ -61,8 +68,9 @@ impl Factor for Fat {
     }
 }
 
-#[wasm_bindgen]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Vitamins {
     pub value: f64,
 }
@@ -77,8 +85,9 @@ impl Factor for Vitamins {
     }
 }
 
-#[wasm_bindgen]
-#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Wealth {
     pub value: f64,
 }
@@ -98,8 +107,8 @@ impl Factor for Wealth {
 
 /// Beans each have a set of factors, along with "global facotrs", that are shared among all beans,
 /// such as a global food shortage factor.
-#[wasm_bindgen]
-#[derive(Debug, Clone)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Factors {
     // recreation related factors
     pub alcoholism: bool,
@@ -125,11 +134,18 @@ pub struct Factors {
 
     // wealth
     pub wealth: Wealth,
+
+    // how much of the habits above the bean uses, see exposure.rs. Habits it doesn't have are 0
+    #[serde(default)]
+    pub exposures: Exposures,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Factors {
-    #[wasm_bindgen(constructor)]
+    /// Factors without any doses, so the habits carry the relative risks of the hazard table as
+    /// they are.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         alcoholism: bool,
         binge_drinker: bool,
@@ -155,6 +171,197 @@ impl Factors {
             fat,
             vitamins,
             wealth,
+            exposures: Exposures::default(),
+        }
+    }
+}
+
+impl Factors {
+    /// Clears every recreational habit, leaving the diet and wealth alone. Used for newborns, who
+    /// haven't smoked anything yet either.
+    pub fn clear_habits(&mut self) {
+        self.exposures.pack_years = 0.0;
+
+        for habit in Habit::ALL {
+            self.set(habit, false);
+        }
+    }
+}
+
+/// The recreational habits a bean can have, one for each boolean in `Factors`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Habit {
+    Alcoholism,
+    BingeDrinker,
+    SmokesWeed,
+    SmokesCigarettes,
+    SmokesVape,
+    ConsumesHardDrugs,
+}
+
+impl Habit {
+    pub const COUNT: usize = 6;
+
+    pub const ALL: [Habit; Habit::COUNT] = [
+        Habit::Alcoholism,
+        Habit::BingeDrinker,
+        Habit::SmokesWeed,
+        Habit::SmokesCigarettes,
+        Habit::SmokesVape,
+        Habit::ConsumesHardDrugs,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The name of the habit, the same as its field in `Factors`.
+    pub fn key(self) -> &'static str {
+        match self {
+            Habit::Alcoholism => "alcoholism",
+            Habit::BingeDrinker => "binge_drinker",
+            Habit::SmokesWeed => "smokes_weed",
+            Habit::SmokesCigarettes => "smokes_cigarettes",
+            Habit::SmokesVape => "smokes_vape",
+            Habit::ConsumesHardDrugs => "consumes_hard_drugs",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Habit> {
+        Habit::ALL.into_iter().find(|habit| habit.key() == key)
+    }
+
+    /// The share of the population with this habit in the settings. Binge drinking is set as a
+    /// share of drinkers (`binge_share`) instead, as it is a kind of drinking.
+    pub fn rate(self, settings: &Settings) -> Option<f64> {
+        match self {
+            Habit::Alcoholism => Some(settings.drinkers),
+            Habit::BingeDrinker => None,
+            Habit::SmokesWeed => Some(settings.weed_smokers),
+            Habit::SmokesCigarettes => Some(settings.smokers),
+            Habit::SmokesVape => Some(settings.vapers),
+            Habit::ConsumesHardDrugs => Some(settings.hard_drugger),
+        }
+    }
+}
+
+/// How many times as common each habit is among men as among women. The rates in the settings
+/// are for everyone, and are split between the sexes so that the average stays the same.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HabitSexRatios {
+    pub alcoholism: f64,
+
+    pub smokes_weed: f64,
+
+    pub smokes_cigarettes: f64,
+
+    pub smokes_vape: f64,
+
+    pub consumes_hard_drugs: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl HabitSexRatios {
+    /// Men are more likely to have every habit, roughly as in surveys of drug use, where men
+    /// smoke and drink around a third more often and use hard drugs about twice as often.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> HabitSexRatios {
+        HabitSexRatios {
+            alcoholism: 1.3,
+            smokes_weed: 1.6,
+            smokes_cigarettes: 1.4,
+            smokes_vape: 1.3,
+            consumes_hard_drugs: 2.0,
+        }
+    }
+
+    /// Every habit as common among women as among men.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn none() -> HabitSexRatios {
+        HabitSexRatios {
+            alcoholism: 1.0,
+            smokes_weed: 1.0,
+            smokes_cigarettes: 1.0,
+            smokes_vape: 1.0,
+            consumes_hard_drugs: 1.0,
+        }
+    }
+}
+
+impl HabitSexRatios {
+    /// The ratio for a habit. Binge drinking follows from drinking, so it has none.
+    pub fn get(&self, habit: Habit) -> Option<f64> {
+        match habit {
+            Habit::Alcoholism => Some(self.alcoholism),
+            Habit::BingeDrinker => None,
+            Habit::SmokesWeed => Some(self.smokes_weed),
+            Habit::SmokesCigarettes => Some(self.smokes_cigarettes),
+            Habit::SmokesVape => Some(self.smokes_vape),
+            Habit::ConsumesHardDrugs => Some(self.consumes_hard_drugs),
+        }
+    }
+}
+
+impl Default for HabitSexRatios {
+    fn default() -> Self {
+        HabitSexRatios::new()
+    }
+}
+
+impl Habit {
+    /// The share of one sex with this habit. With a ratio of `r` between men and women, women
+    /// get `2 / (1 + r)` times the rate in the settings and men `r` times that, which averages
+    /// out to the rate in the settings (as long as neither goes over 1).
+    pub fn rate_for(self, settings: &Settings, sex: Sex) -> Option<f64> {
+        let rate = self.rate(settings)?;
+        let ratio = settings.habit_sex_ratios.get(self).unwrap_or(1.0);
+
+        let female = 2.0 * rate / (1.0 + ratio);
+
+        Some(match sex {
+            Sex::Female => female.min(1.0),
+            Sex::Male => (female * ratio).min(1.0),
+        })
+    }
+}
+
+impl Factors {
+    pub fn has(&self, habit: Habit) -> bool {
+        match habit {
+            Habit::Alcoholism => self.alcoholism,
+            Habit::BingeDrinker => self.binge_drinker,
+            Habit::SmokesWeed => self.smokes_weed,
+            Habit::SmokesCigarettes => self.smokes_cigarettes,
+            Habit::SmokesVape => self.smokes_vape,
+            Habit::ConsumesHardDrugs => self.consumes_hard_drugs,
         }
     }
+
+    /// Gives the bean a habit or takes it away. Without the habit, the bean uses none of it, but
+    /// pack-years stay. A bean given a habit keeps whatever amount it has, see `Exposures::amount`.
+    pub fn set(&mut self, habit: Habit, value: bool) {
+        if !value {
+            self.exposures.set_amount(habit, 0.0);
+        }
+
+        match habit {
+            Habit::Alcoholism => self.alcoholism = value,
+            Habit::BingeDrinker => self.binge_drinker = value,
+            Habit::SmokesWeed => self.smokes_weed = value,
+            Habit::SmokesCigarettes => self.smokes_cigarettes = value,
+            Habit::SmokesVape => self.smokes_vape = value,
+            Habit::ConsumesHardDrugs => self.consumes_hard_drugs = value,
+        }
+    }
+
+    /// Moves the diet by however much the settings moved between two years.
+    pub fn shift_diet(&mut self, before: &Settings, after: &Settings) {
+        self.sugar.value = (self.sugar.value + after.sugar - before.sugar).max(0.0);
+        self.salt.value = (self.salt.value + after.salt - before.salt).max(0.0);
+        self.fat.value = (self.fat.value + after.fat - before.fat).max(0.0);
+        self.vitamins.value = (self.vitamins.value + after.vitamins - before.vitamins).max(0.0);
+    }
 }
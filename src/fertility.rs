@@ -0,0 +1,88 @@
+//! Fertility for Beanreadings. Births come from an age-specific fertility schedule, scaled so that
+//! a woman who lives through every fertile year has `Settings::fertility_rate` children on
+//! average (the total fertility rate).
+//!
+//! The built in schedule is the typical shape of the UN World Population Prospects age-specific
+//! fertility rates: fertility peaks in the late twenties and is close to zero after 45.
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Share of lifetime fertility in each five year age band from 15-19 to 45-49.
+const DEFAULT_BANDS: [f64; 7] = [0.05, 0.2, 0.3, 0.265, 0.14, 0.04, 0.005];
+
+/// The first age of the first band in `DEFAULT_BANDS`.
+const DEFAULT_FIRST_AGE: usize = 15;
+
+/// The shape of fertility over a woman's life. The weights are indexed by age, and only their
+/// relative sizes matter, as the schedule is always scaled to the total fertility rate.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FertilitySchedule {
+    weights: Vec<f64>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl FertilitySchedule {
+    /// Creates a schedule from one weight per year of age, starting at age 0. Weights can be
+    /// actual age-specific fertility rates or any other numbers with the right shape.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(weights: Vec<f64>) -> FertilitySchedule {
+        FertilitySchedule { weights }
+    }
+
+    /// Creates a schedule from weights for five year age bands, starting at `first_age`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn from_bands(first_age: u32, band_weights: Vec<f64>) -> FertilitySchedule {
+        let mut weights = vec![0.0; first_age as usize];
+
+        for weight in band_weights {
+            weights.extend([weight / 5.0; 5]);
+        }
+
+        FertilitySchedule { weights }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_weights(&self) -> Vec<f64> {
+        self.weights.clone()
+    }
+
+    /// Age-specific fertility rates, the chance of a woman giving birth at each age, for a total
+    /// fertility rate. Ages past the end of the list have a fertility rate of zero.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn rates(&self, fertility_rate: f64) -> Vec<f64> {
+        let total: f64 = self.weights.iter().sum();
+
+        if total <= 0.0 {
+            return vec![0.0; self.weights.len()];
+        }
+
+        self.weights
+            .iter()
+            .map(|weight| weight / total * fertility_rate)
+            .collect()
+    }
+}
+
+impl Default for FertilitySchedule {
+    fn default() -> Self {
+        FertilitySchedule::from_bands(DEFAULT_FIRST_AGE as u32, DEFAULT_BANDS.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rates_sum_to_fertility_rate() {
+        let rates = FertilitySchedule::default().rates(2.1);
+
+        assert!((rates.iter().sum::<f64>() - 2.1).abs() < 1e-9);
+        assert_eq!(rates[14], 0.0);
+        assert!(rates[27] > rates[17]);
+        assert_eq!(rates.len(), 50);
+    }
+}
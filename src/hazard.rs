@@ -0,0 +1,492 @@
+//! Hazard tables for Beanreadings. The chance of a bean dying of each cause comes from a table
+//! instead of being written into the code, so the numbers can be changed without touching Rust.
+//!
+//! A table has a baseline risk for every cause in each age band, and relative risks for the
+//! factors that make a cause more (or less) likely. A bean's risk of a cause is the baseline for
+//! its age, multiplied by the relative risk of every factor it has. Causes and factors that are
+//! left out have a baseline of 0 and a relative risk of 1. Tables are JSON, for example:
+//!
+//! ```json
+//! {
+//!     "version": 1,
+//!     "bands": [
+//!         { "from": 0, "to": 49, "baseline": { "infection": 0.001 } },
+//!         {
+//!             "from": 50,
+//!             "baseline": { "infection": 0.001, "cancer": 0.003 },
+//!             "female": { "cancer": 0.0026 },
+//!             "male": { "cancer": 0.0034 }
+//!         }
+//!     ],
+//!     "relative_risks": {
+//!         "smokes_cigarettes": { "cancer": 1.7 }
+//!     }
+//! }
+//! ```
+//!
+//! The bands have to start at age 0, follow on from each other, and the last one has no `to`, so
+//! every age is covered. Old age is not in the table, beans past `max_age` always die of it.
+//! A band can give women and men their own risks of some causes in `female` and `male`, and the
+//! other causes use the `baseline` for both.
+//!
+//! The default table (`hazards.json`) is built into the binary. It is calibrated so that, with the
+//! default settings, `Simulation::hazard_life_table` gives a life expectancy at birth of about 75.9
+//! years for women and 71.3 for men, close to the world in 2019:
+//! - [WHO Global Health Observatory, Life expectancy and healthy life expectancy](https://www.who.int/data/gho/data/themes/mortality-and-global-health-estimates/ghe-life-expectancy-and-healthy-life-expectancy)
+//!   life expectancy at birth was 73.3 years, 75.9 for women and 70.8 for men.
+//!
+//! The chance of dying in each band follows the shape of that life table: about 3% in the first
+//! year, less than 1 in 400 a year from 1 to 40, and then roughly doubling every 8 years, so
+//! about 96% of beans live to 15. Within a band, the split between causes is a rough guess after
+//! the leading causes of death by age in the Global Burden of Disease study (injuries are not a
+//! cause here, so their share is spread over the others), and the baselines are scaled so that the
+//! average bean, with the default habits, diet and wealth, has the band's chance of dying. From
+//! age 15, men are more likely to die of heart disease, cancer, respiratory and liver disease and
+//! infections, and women of dementia. The relative risks are the old fixed increments, divided by
+//! the baselines they were added to in the first version of the table.
+
+use crate::bean::Sex;
+use crate::causes::CauseOfDeath;
+use crate::factors::{Factors, Habit};
+use crate::types::invalid_parameters;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// The version of the table format this build understands.
+pub const HAZARD_TABLE_VERSION: u32 = 1;
+
+const DEFAULT_TABLE: &str = include_str!("hazards.json");
+
+/// Something about a bean that changes its risk of dying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RiskFactor {
+    Alcoholism,
+    BingeDrinker,
+    SmokesWeed,
+    SmokesCigarettes,
+    SmokesVape,
+    ConsumesHardDrugs,
+    SugarAboveRdi,
+    SaltAboveRdi,
+    FatAboveRdi,
+    VitaminsBelowRdi,
+}
+
+impl RiskFactor {
+    pub const COUNT: usize = 10;
+
+    pub const ALL: [RiskFactor; RiskFactor::COUNT] = [
+        RiskFactor::Alcoholism,
+        RiskFactor::BingeDrinker,
+        RiskFactor::SmokesWeed,
+        RiskFactor::SmokesCigarettes,
+        RiskFactor::SmokesVape,
+        RiskFactor::ConsumesHardDrugs,
+        RiskFactor::SugarAboveRdi,
+        RiskFactor::SaltAboveRdi,
+        RiskFactor::FatAboveRdi,
+        RiskFactor::VitaminsBelowRdi,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The name of the factor in hazard tables.
+    pub fn key(self) -> &'static str {
+        match self {
+            RiskFactor::Alcoholism => "alcoholism",
+            RiskFactor::BingeDrinker => "binge_drinker",
+            RiskFactor::SmokesWeed => "smokes_weed",
+            RiskFactor::SmokesCigarettes => "smokes_cigarettes",
+            RiskFactor::SmokesVape => "smokes_vape",
+            RiskFactor::ConsumesHardDrugs => "consumes_hard_drugs",
+            RiskFactor::SugarAboveRdi => "sugar_above_rdi",
+            RiskFactor::SaltAboveRdi => "salt_above_rdi",
+            RiskFactor::FatAboveRdi => "fat_above_rdi",
+            RiskFactor::VitaminsBelowRdi => "vitamins_below_rdi",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<RiskFactor> {
+        RiskFactor::ALL
+            .into_iter()
+            .find(|factor| factor.key() == key)
+    }
+
+    /// Whether a bean with these factors has this risk factor.
+    pub fn present(self, factors: &Factors) -> bool {
+        match self {
+            RiskFactor::Alcoholism => factors.alcoholism,
+            RiskFactor::BingeDrinker => factors.binge_drinker,
+            RiskFactor::SmokesWeed => factors.smokes_weed,
+            RiskFactor::SmokesCigarettes => factors.smokes_cigarettes,
+            RiskFactor::SmokesVape => factors.smokes_vape,
+            RiskFactor::ConsumesHardDrugs => factors.consumes_hard_drugs,
+            RiskFactor::SugarAboveRdi => factors.sugar.value > 1.0,
+            RiskFactor::SaltAboveRdi => factors.salt.value > 1.0,
+            RiskFactor::FatAboveRdi => factors.fat.value > 1.0,
+            RiskFactor::VitaminsBelowRdi => factors.vitamins.value < 1.0,
+        }
+    }
+}
+
+impl From<Habit> for RiskFactor {
+    fn from(habit: Habit) -> RiskFactor {
+        match habit {
+            Habit::Alcoholism => RiskFactor::Alcoholism,
+            Habit::BingeDrinker => RiskFactor::BingeDrinker,
+            Habit::SmokesWeed => RiskFactor::SmokesWeed,
+            Habit::SmokesCigarettes => RiskFactor::SmokesCigarettes,
+            Habit::SmokesVape => RiskFactor::SmokesVape,
+            Habit::ConsumesHardDrugs => RiskFactor::ConsumesHardDrugs,
+        }
+    }
+}
+
+/// The baseline risks for a range of ages, `to` included. The last band has no end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgeBand {
+    pub from: u32,
+    pub to: Option<u32>,
+    pub baseline: [f64; CauseOfDeath::COUNT],
+    pub by_sex: [[f64; CauseOfDeath::COUNT]; 2], // the baseline with each sex's own risks, by `Sex::index`
+}
+
+impl AgeBand {
+    pub fn contains(&self, age: u32) -> bool {
+        age >= self.from && self.to.is_none_or(|to| age <= to)
+    }
+
+    /// The baseline risks for one sex.
+    pub fn baseline_for(&self, sex: Sex) -> &[f64; CauseOfDeath::COUNT] {
+        &self.by_sex[sex.index()]
+    }
+}
+
+/// A validated hazard table, see the module docs for the format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "TableFile", into = "TableFile")]
+pub struct HazardTable {
+    bands: Vec<AgeBand>,
+    relative_risks: [[f64; CauseOfDeath::COUNT]; RiskFactor::COUNT],
+}
+
+impl HazardTable {
+    /// Reads and validates a table from JSON.
+    pub fn from_json(json: &str) -> Result<HazardTable, String> {
+        serde_json::from_str(json).map_err(invalid_parameters)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// The table built into the binary.
+    pub fn builtin() -> &'static HazardTable {
+        static TABLE: OnceLock<HazardTable> = OnceLock::new();
+
+        TABLE.get_or_init(|| {
+            HazardTable::from_json(DEFAULT_TABLE).expect("the built in hazard table is valid")
+        })
+    }
+
+    pub fn bands(&self) -> &[AgeBand] {
+        &self.bands
+    }
+
+    /// The band an age falls into. Validation makes sure there always is one.
+    pub fn band(&self, age: u32) -> &AgeBand {
+        self.bands
+            .iter()
+            .find(|band| band.contains(age))
+            .unwrap_or(&self.bands[self.bands.len() - 1])
+    }
+
+    pub fn relative_risk(&self, factor: RiskFactor, cause: CauseOfDeath) -> f64 {
+        self.relative_risks[factor.index()][cause.index()]
+    }
+
+    /// The chance of dying of each cause this year for a bean of this age and sex with these
+    /// factors.
+    pub fn risks(&self, age: u32, sex: Sex, factors: &Factors) -> [f64; CauseOfDeath::COUNT] {
+        let mut risks = *self.band(age).baseline_for(sex);
+
+        for factor in RiskFactor::ALL {
+            if factor.present(factors) {
+                for (risk, relative_risk) in
+                    risks.iter_mut().zip(self.relative_risks[factor.index()])
+                {
+                    *risk *= relative_risk;
+                }
+            }
+        }
+
+        risks
+    }
+}
+
+impl Default for HazardTable {
+    fn default() -> Self {
+        HazardTable::builtin().clone()
+    }
+}
+
+// the table as it is written in JSON, with names instead of indices
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableFile {
+    version: u32,
+    bands: Vec<BandFile>,
+    #[serde(default)]
+    relative_risks: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BandFile {
+    from: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    to: Option<u32>,
+    #[serde(default)]
+    baseline: BTreeMap<String, f64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    female: BTreeMap<String, f64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    male: BTreeMap<String, f64>,
+}
+
+fn cause_from_key(key: &str) -> Result<CauseOfDeath, String> {
+    match CauseOfDeath::from_key(key) {
+        Some(CauseOfDeath::OldAge) => Err("old_age comes from max_age, not the table".to_string()),
+        Some(cause) => Ok(cause),
+        None => Err(format!("unknown cause of death {}", key)),
+    }
+}
+
+impl TryFrom<TableFile> for HazardTable {
+    type Error = String;
+
+    fn try_from(file: TableFile) -> Result<Self, Self::Error> {
+        if file.version != HAZARD_TABLE_VERSION {
+            return Err(format!(
+                "table version {} is not supported, expected {}",
+                file.version, HAZARD_TABLE_VERSION
+            ));
+        }
+
+        if file.bands.is_empty() {
+            return Err("the table has no age bands".to_string());
+        }
+
+        let mut bands = Vec::with_capacity(file.bands.len());
+        let mut next_age = Some(0); // the age the next band has to start at
+
+        for band in file.bands {
+            let Some(expected) = next_age else {
+                return Err(format!(
+                    "the band from {} comes after an open band",
+                    band.from
+                ));
+            };
+
+            if band.from != expected {
+                return Err(format!(
+                    "the band from {} should start at {}",
+                    band.from, expected
+                ));
+            }
+
+            if band.to.is_some_and(|to| to < band.from) {
+                return Err(format!("the band from {} ends before it starts", band.from));
+            }
+
+            let risks = |named: BTreeMap<String, f64>, baseline: [f64; CauseOfDeath::COUNT]| {
+                let mut risks = baseline;
+
+                for (key, risk) in named {
+                    let cause = cause_from_key(&key)?;
+
+                    if !(0.0..=1.0).contains(&risk) {
+                        return Err(format!(
+                            "the risk of {} from age {} should be between 0 and 1",
+                            key, band.from
+                        ));
+                    }
+
+                    risks[cause.index()] = risk;
+                }
+
+                Ok(risks)
+            };
+
+            // each sex has the baseline, apart from the causes it has its own risks for
+
+            let baseline = risks(band.baseline, [0.0; CauseOfDeath::COUNT])?;
+            let by_sex = [risks(band.female, baseline)?, risks(band.male, baseline)?];
+
+            next_age = band.to.map(|to| to + 1);
+
+            bands.push(AgeBand {
+                from: band.from,
+                to: band.to,
+                baseline,
+                by_sex,
+            });
+        }
+
+        if next_age.is_some() {
+            return Err("the last band should have no end".to_string());
+        }
+
+        let mut relative_risks = [[1.0; CauseOfDeath::COUNT]; RiskFactor::COUNT];
+
+        for (factor_key, causes) in file.relative_risks {
+            let factor = RiskFactor::from_key(&factor_key)
+                .ok_or_else(|| format!("unknown risk factor {}", factor_key))?;
+
+            for (key, relative_risk) in causes {
+                let cause = cause_from_key(&key)?;
+
+                if !relative_risk.is_finite() || relative_risk < 0.0 {
+                    return Err(format!(
+                        "the relative risk of {} for {} should be a positive number",
+                        key, factor_key
+                    ));
+                }
+
+                relative_risks[factor.index()][cause.index()] = relative_risk;
+            }
+        }
+
+        Ok(HazardTable {
+            bands,
+            relative_risks,
+        })
+    }
+}
+
+impl From<HazardTable> for TableFile {
+    fn from(table: HazardTable) -> Self {
+        let named = |values: &[f64; CauseOfDeath::COUNT], default: f64| {
+            CauseOfDeath::ALL
+                .into_iter()
+                .filter(|cause| values[cause.index()] != default)
+                .map(|cause| (cause.key().to_string(), values[cause.index()]))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let bands = table
+            .bands
+            .iter()
+            .map(|band| {
+                let differences = |sex: Sex| {
+                    CauseOfDeath::ALL
+                        .into_iter()
+                        .filter(|cause| {
+                            band.baseline_for(sex)[cause.index()] != band.baseline[cause.index()]
+                        })
+                        .map(|cause| {
+                            (
+                                cause.key().to_string(),
+                                band.baseline_for(sex)[cause.index()],
+                            )
+                        })
+                        .collect()
+                };
+
+                BandFile {
+                    from: band.from,
+                    to: band.to,
+                    baseline: named(&band.baseline, 0.0),
+                    female: differences(Sex::Female),
+                    male: differences(Sex::Male),
+                }
+            })
+            .collect();
+
+        let relative_risks = RiskFactor::ALL
+            .into_iter()
+            .map(|factor| {
+                (
+                    factor.key().to_string(),
+                    named(&table.relative_risks[factor.index()], 1.0),
+                )
+            })
+            .filter(|(_, causes)| !causes.is_empty())
+            .collect();
+
+        TableFile {
+            version: HAZARD_TABLE_VERSION,
+            bands,
+            relative_risks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::factors::*;
+
+    fn factors(smokes: bool) -> Factors {
+        Factors {
+            alcoholism: false,
+            binge_drinker: false,
+            smokes_weed: false,
+            smokes_cigarettes: smokes,
+            smokes_vape: false,
+            consumes_hard_drugs: false,
+            sugar: Sugar { value: 0.5 },
+            salt: Salt { value: 0.5 },
+            fat: Fat { value: 0.5 },
+            vitamins: Vitamins { value: 1.5 },
+            wealth: Wealth { value: 0.5 },
+            exposures: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_builtin_table() {
+        let table = HazardTable::builtin();
+
+        // children die of childhood causes, and only adults get dementia
+
+        let child = table.risks(5, Sex::Female, &factors(false));
+        let adult = table.risks(60, Sex::Female, &factors(false));
+
+        assert!(child[CauseOfDeath::DiarrhealDisease.index()] > 0.0);
+        assert_eq!(adult[CauseOfDeath::DiarrhealDisease.index()], 0.0);
+        assert_eq!(child[CauseOfDeath::Dimentia.index()], 0.0);
+        assert!(adult[CauseOfDeath::Dimentia.index()] > 0.0);
+
+        let smoker = table.risks(60, Sex::Female, &factors(true));
+        let cancer = CauseOfDeath::Cancer.index();
+
+        assert!((smoker[cancer] / adult[cancer] - 1.7).abs() < 1e-12);
+
+        assert_eq!(&HazardTable::from_json(&table.to_json()).unwrap(), table);
+    }
+
+    #[test]
+    fn test_invalid_tables() {
+        let gap = r#"{ "version": 1, "bands": [
+            { "from": 0, "to": 9 }, { "from": 11 }
+        ] }"#;
+
+        let closed = r#"{ "version": 1, "bands": [{ "from": 0, "to": 9 }] }"#;
+
+        let risk = r#"{ "version": 1, "bands": [{ "from": 0, "baseline": { "cancer": 2.0 } }] }"#;
+
+        let factor = r#"{ "version": 1, "bands": [{ "from": 0 }],
+            "relative_risks": { "juggling": { "cancer": 2.0 } } }"#;
+
+        for json in [gap, closed, risk, factor] {
+            assert!(HazardTable::from_json(json).is_err(), "{}", json);
+        }
+
+        assert!(HazardTable::from_json(r#"{ "version": 1, "bands": [{ "from": 0 }] }"#).is_ok());
+    }
+}
@@ -11,15 +11,51 @@
 
 // Importing our modules
 
+mod accuracy;
 mod age;
 mod bean;
+mod behaviors;
+mod brnn;
+mod causes;
+mod cohort;
+mod distributions;
+mod ensemble;
+mod error;
+mod exposure;
 mod factors;
+mod fertility;
+mod hazard;
+mod life_table;
+mod policy;
+mod rng;
+mod scenario;
 mod simul;
+mod surrogate;
+mod transitions;
 mod types;
+mod wealth;
 
 // RE EXPORTS
 
+pub use accuracy::*;
 pub use age::*;
 pub use bean::*;
+pub use behaviors::*;
+pub use brnn::*;
+pub use causes::*;
+pub use cohort::*;
+pub use distributions::*;
+pub use ensemble::*;
+pub use error::*;
+pub use exposure::*;
 pub use factors::*;
+pub use fertility::*;
+pub use hazard::*;
+pub use life_table::*;
+pub use policy::*;
+pub use rng::*;
+pub use scenario::*;
+pub use surrogate::*;
+pub use transitions::*;
 pub use types::*;
+pub use wealth::*;
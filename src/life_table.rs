@@ -0,0 +1,371 @@
+//! Life tables for Beanreadings. Head counts depend on how old the population happens to be, so
+//! mortality is also summed up the way demographers do it: as a life table, and the life
+//! expectancy that comes out of it.
+//!
+//! Every table starts at age 0 and ends at `max_age + 1`, when everyone dies of old age. From the
+//! chance of dying at each age (qx) we work out the survivors out of 100000 births (lx), the
+//! deaths at each age (dx), the years lived at each age (Lx), the years left to everyone alive at
+//! an age (Tx) and the life expectancy (ex). Deaths are taken to happen halfway through the year.
+//!
+//! A table can come from the deaths and beans alive in a simulation, either in one year (a period
+//! table) or following the beans born in one year as they get older (a cohort table). It can also
+//! come straight from the hazard model, without simulating anyone: a sample of risk profiles is
+//! drawn from the settings, like the cohort engine does, and followed from birth with the chances
+//! of dying from `Bean::risks`. Profiles have their habits from `ADULT_AGE` on, like beans that
+//! come of age without habit transitions, and habit transitions aren't followed.
+
+use crate::bean::{Bean, Sex, ADULT_AGE};
+use crate::cohort::DEFAULT_PROFILES;
+use crate::factors::Factors;
+use crate::rng::{derive_seed, seeded};
+use crate::types::{Settings, Simulation};
+use serde::Serialize;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// How many births every life table starts with.
+pub const RADIX: f64 = 100_000.0;
+
+/// The columns of a life table, by age.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LifeTable {
+    qx: Vec<f64>, // chance of dying before the next birthday
+
+    lx: Vec<f64>, // alive at each birthday, out of `RADIX` births
+
+    dx: Vec<f64>, // dying between each birthday and the next
+
+    #[serde(rename = "Lx")]
+    big_lx: Vec<f64>, // years lived between each birthday and the next
+
+    #[serde(rename = "Tx")]
+    tx: Vec<f64>, // years lived from each birthday on
+
+    ex: Vec<f64>, // life expectancy at each age
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl LifeTable {
+    /// A life table from the chance of dying at each age. The last age is taken as the oldest
+    /// anyone gets, so everyone still alive dies there.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(chances: Vec<f64>) -> LifeTable {
+        let ages = chances.len().max(1);
+
+        let qx: Vec<f64> = (0..ages)
+            .map(|age| match chances.get(age) {
+                _ if age == ages - 1 => 1.0,
+                Some(&chance) if chance.is_finite() => chance.clamp(0.0, 1.0),
+                _ => 0.0,
+            })
+            .collect();
+
+        let mut lx = Vec::with_capacity(ages);
+        let mut dx = Vec::with_capacity(ages);
+        let mut big_lx = Vec::with_capacity(ages);
+
+        let mut alive = RADIX;
+
+        for &chance in &qx {
+            let deaths = alive * chance;
+
+            lx.push(alive);
+            dx.push(deaths);
+            big_lx.push(alive - deaths / 2.0);
+
+            alive -= deaths;
+        }
+
+        let mut tx = big_lx.clone();
+
+        for age in (0..ages - 1).rev() {
+            tx[age] += tx[age + 1];
+        }
+
+        let ex = tx
+            .iter()
+            .zip(&lx)
+            .map(|(&years, &alive)| if alive > 0.0 { years / alive } else { 0.0 })
+            .collect();
+
+        LifeTable {
+            qx,
+            lx,
+            dx,
+            big_lx,
+            tx,
+            ex,
+        }
+    }
+
+    /// A life table from the deaths at each age and the beans alive at the start of the year.
+    /// Ages nobody was alive at get the chance of dying of the age before them.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn from_deaths(deaths: Vec<f64>, exposure: Vec<f64>) -> LifeTable {
+        let mut chance = 0.0;
+
+        let chances = exposure
+            .iter()
+            .enumerate()
+            .map(|(age, &alive)| {
+                if alive > 0.0 {
+                    chance = deaths.get(age).copied().unwrap_or(0.0) / alive;
+                }
+
+                chance
+            })
+            .collect();
+
+        LifeTable::new(chances)
+    }
+
+    /// The oldest age in the table, when everyone left dies.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_oldest_age(&self) -> u32 {
+        self.qx.len() as u32 - 1
+    }
+
+    /// The chance of dying before the next birthday (qx).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_qx(&self) -> Vec<f64> {
+        self.qx.clone()
+    }
+
+    /// How many of 100000 births are alive at each birthday (lx).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_lx(&self) -> Vec<f64> {
+        self.lx.clone()
+    }
+
+    /// How many of 100000 births die at each age (dx).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_dx(&self) -> Vec<f64> {
+        self.dx.clone()
+    }
+
+    /// The years lived at each age by 100000 births (Lx).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_big_lx(&self) -> Vec<f64> {
+        self.big_lx.clone()
+    }
+
+    /// The years lived from each age on by 100000 births (Tx).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_tx(&self) -> Vec<f64> {
+        self.tx.clone()
+    }
+
+    /// The life expectancy at each age (ex).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_ex(&self) -> Vec<f64> {
+        self.ex.clone()
+    }
+
+    /// How many more years a bean of an age can expect to live. Past the oldest age, that is the
+    /// half year it dies in.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn life_expectancy_at(&self, age: u32) -> f64 {
+        self.ex.get(age as usize).copied().unwrap_or(0.5)
+    }
+
+    /// The life expectancy at birth.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn life_expectancy(&self) -> f64 {
+        self.life_expectancy_at(0)
+    }
+
+    /// The whole table as JSON, with a column for each of qx, lx, dx, Lx, Tx and ex.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+impl LifeTable {
+    /// A life table straight from the hazard model, following `profiles` risk profiles of a sex
+    /// from birth. `settings_at` gives the settings in effect at each age, so the same settings
+    /// throughout give a period table and the settings of each year a cohort table.
+    pub fn from_hazards(
+        settings: &Settings,
+        sex: Sex,
+        profiles: u32,
+        settings_at: impl Fn(u32) -> Settings,
+    ) -> LifeTable {
+        let profiles = profiles.max(1) as u64;
+        let ages = settings.max_age + 2;
+
+        let mut alive = vec![1.0; profiles as usize];
+        let mut chances = Vec::with_capacity(ages as usize);
+
+        let mut drawn: Option<(Settings, Vec<Factors>)> = None;
+
+        for age in 0..ages {
+            let effective = settings_at(age);
+
+            // every profile draws from its own stream, so a profile is the same bean in every
+            // year and only changes where the settings do
+
+            if drawn.as_ref().is_none_or(|(last, _)| *last != effective) {
+                let factors = (0..profiles)
+                    .map(|profile| {
+                        let mut rng = seeded(derive_seed(settings.seed, profile));
+
+                        Bean::with_age(&effective, 0, sex, &mut rng).get_factors()
+                    })
+                    .collect();
+
+                drawn = Some((effective, factors));
+            }
+
+            let Some((effective, factors)) = &drawn else {
+                continue;
+            };
+
+            let mut living = 0.0;
+            let mut dying = 0.0;
+
+            for (alive, factors) in alive.iter_mut().zip(factors) {
+                let mut factors = factors.clone();
+
+                if age < ADULT_AGE {
+                    factors.clear_habits();
+                } else if factors.smokes_cigarettes {
+                    factors.exposures.pack_years = factors.exposures.pack_years_at(age);
+                }
+
+                let total: f64 = Bean::from_parts(age, sex, factors)
+                    .risks(effective)
+                    .iter()
+                    .sum();
+                let deaths = *alive * total.min(1.0);
+
+                living += *alive;
+                dying += deaths;
+                *alive -= deaths;
+            }
+
+            chances.push(if living > 0.0 { dying / living } else { 1.0 });
+        }
+
+        LifeTable::new(chances)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Simulation {
+    /// The period life table of a sex from the hazard model, with the settings in effect in a
+    /// year of the simulation.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn hazard_life_table(&self, sex: Sex, year: u32) -> LifeTable {
+        let effective = self.settings.at_year(year);
+
+        LifeTable::from_hazards(&self.settings, sex, DEFAULT_PROFILES, |_| effective.clone())
+    }
+
+    /// The cohort life table of a sex from the hazard model, for beans born in a year of the
+    /// simulation. Each age has the settings of the year the cohort reaches it, so it lives
+    /// through every policy.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn hazard_cohort_life_table(&self, sex: Sex, born: u32) -> LifeTable {
+        LifeTable::from_hazards(&self.settings, sex, DEFAULT_PROFILES, |age| {
+            self.settings.at_year(born + age)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::policy::{Parameter, Policy, Schedule};
+
+    #[test]
+    fn test_life_table() {
+        // a chance of 1/2 every year, then certain death at 2
+
+        let table = LifeTable::new(vec![0.5, 0.5, 0.5]);
+
+        assert_eq!(table.get_qx(), vec![0.5, 0.5, 1.0]);
+        assert_eq!(table.get_lx(), vec![100000.0, 50000.0, 25000.0]);
+        assert_eq!(table.get_dx(), vec![50000.0, 25000.0, 25000.0]);
+        assert_eq!(table.get_big_lx(), vec![75000.0, 37500.0, 12500.0]);
+        assert_eq!(table.get_tx(), vec![125000.0, 50000.0, 12500.0]);
+        assert_eq!(table.life_expectancy(), 1.25);
+        assert_eq!(table.life_expectancy_at(2), 0.5);
+
+        // ages nobody was alive at carry on with the chance before them
+
+        let table = LifeTable::from_deaths(vec![10.0, 0.0, 0.0], vec![100.0, 0.0, 0.0]);
+
+        assert_eq!(table.get_qx(), vec![0.1, 0.1, 1.0]);
+        assert!(table.to_json().contains("\"Lx\""));
+
+        // the hazard model gives men a shorter life, and a policy that gets rid of smoking helps
+        // the beans born after it more than the period table before it
+
+        let mut settings = Settings::new();
+        settings.set_seed(25);
+        settings.set_smokers(0.4);
+
+        let simulation = Simulation::new(settings.clone());
+
+        let female = simulation.hazard_life_table(Sex::Female, 0);
+        let male = simulation.hazard_life_table(Sex::Male, 0);
+
+        assert!(female.life_expectancy() > male.life_expectancy());
+        assert!(female.life_expectancy_at(60) < female.life_expectancy());
+
+        settings.add_policy(Policy::new(
+            Parameter::Smokers,
+            Schedule::Step {
+                year: 1,
+                value: 0.0,
+            },
+        ));
+
+        let simulation = Simulation::new(settings);
+
+        assert_eq!(simulation.hazard_life_table(Sex::Male, 0), male);
+        assert!(
+            simulation
+                .hazard_cohort_life_table(Sex::Male, 1)
+                .life_expectancy()
+                > male.life_expectancy()
+        );
+    }
+
+    #[test]
+    fn test_engines_agree() {
+        // the cohort engine expects fractions of a death at most ages in a small population, so
+        // its life tables only work if those aren't rounded away. A single agent run of a
+        // thousand beans is noisy, so we compare with the mean of a few.
+
+        let mut settings = Settings::new();
+        settings.set_population(1000);
+        settings.set_years(30);
+
+        let seeds = [3, 4, 5, 6, 7];
+
+        for sex in Sex::ALL {
+            let mut cohort = 0.0;
+            let mut agents = 0.0;
+
+            for seed in seeds {
+                settings.set_seed(seed);
+
+                let simulation = Simulation::new(settings.clone());
+
+                cohort += simulation.cohort().get_life_expectancy(sex) / seeds.len() as f64;
+                agents += simulation.long().get_life_expectancy(sex) / seeds.len() as f64;
+            }
+
+            assert!(
+                (cohort - agents).abs() < 3.0,
+                "{:?}: cohort {} and agents {}",
+                sex,
+                cohort,
+                agents
+            );
+        }
+    }
+}
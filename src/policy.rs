@@ -0,0 +1,222 @@
+//! Policy schedules for Beanreadings. A policy makes one of the settings change over the course of
+//! a simulation, like a cigarette ban in year 5 or a sugar tax that cuts intake over a decade.
+//! The simulation asks the settings for their effective values at the start of every year.
+//!
+//! Policies are plain JSON, so they can be written by hand or from JavaScript, for example:
+//!
+//! ```json
+//! [
+//!     { "parameter": "smokers", "kind": "step", "year": 5, "value": 0.0 },
+//!     { "parameter": "sugar", "kind": "ramp", "start": 0, "end": 10, "from": 0.5, "to": 0.4 },
+//!     { "parameter": "fertility_rate", "kind": "values", "values": [2.1, 2.0, 1.9] }
+//! ]
+//! ```
+
+use crate::types::Settings;
+use serde::{Deserialize, Serialize};
+
+/// The settings that policies can change over time.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Parameter {
+    Smokers,
+    WeedSmokers,
+    Vapers,
+    Drinkers,
+    HardDrugger,
+    Sugar,
+    Salt,
+    Fat,
+    Vitamins,
+    WealthFactor,
+    FertilityRate,
+    SexRatioAtBirth,
+    TwinRate,
+}
+
+impl Parameter {
+    /// Looks a parameter up by the name of its field in `Settings`, e.g. `"hard_drugger"`.
+    pub fn from_name(name: &str) -> Option<Parameter> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+
+    pub fn get(self, settings: &Settings) -> f64 {
+        match self {
+            Parameter::Smokers => settings.smokers,
+            Parameter::WeedSmokers => settings.weed_smokers,
+            Parameter::Vapers => settings.vapers,
+            Parameter::Drinkers => settings.drinkers,
+            Parameter::HardDrugger => settings.hard_drugger,
+            Parameter::Sugar => settings.sugar,
+            Parameter::Salt => settings.salt,
+            Parameter::Fat => settings.fat,
+            Parameter::Vitamins => settings.vitamins,
+            Parameter::WealthFactor => settings.wealth_factor,
+            Parameter::FertilityRate => settings.fertility_rate,
+            Parameter::SexRatioAtBirth => settings.sex_ratio_at_birth,
+            Parameter::TwinRate => settings.twin_rate,
+        }
+    }
+
+    pub fn set(self, settings: &mut Settings, value: f64) {
+        match self {
+            Parameter::Smokers => settings.smokers = value,
+            Parameter::WeedSmokers => settings.weed_smokers = value,
+            Parameter::Vapers => settings.vapers = value,
+            Parameter::Drinkers => settings.drinkers = value,
+            Parameter::HardDrugger => settings.hard_drugger = value,
+            Parameter::Sugar => settings.sugar = value,
+            Parameter::Salt => settings.salt = value,
+            Parameter::Fat => settings.fat = value,
+            Parameter::Vitamins => settings.vitamins = value,
+            Parameter::WealthFactor => settings.wealth_factor = value,
+            Parameter::FertilityRate => settings.fertility_rate = value,
+            Parameter::SexRatioAtBirth => settings.sex_ratio_at_birth = value,
+            Parameter::TwinRate => settings.twin_rate = value,
+        }
+    }
+}
+
+/// How a parameter changes over time. Years count from 0, the first simulated year.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    /// Jumps to `value` in `year`, and stays there.
+    Step { year: u32, value: f64 },
+
+    /// Moves in a straight line from `from` in year `start` to `to` in year `end`, and stays at
+    /// `to` afterwards.
+    Ramp {
+        start: u32,
+        end: u32,
+        from: f64,
+        to: f64,
+    },
+
+    /// One value for every year, starting from year 0. The last value is kept after the list
+    /// runs out.
+    Values { values: Vec<f64> },
+}
+
+impl Schedule {
+    /// The value of the parameter in a year, or `None` if the schedule hasn't started yet.
+    pub fn value_at(&self, year: u32) -> Option<f64> {
+        match self {
+            Schedule::Step { year: from, value } => (year >= *from).then_some(*value),
+            Schedule::Ramp {
+                start,
+                end,
+                from,
+                to,
+            } => {
+                if year < *start {
+                    None
+                } else if year >= *end {
+                    Some(*to)
+                } else {
+                    let progress = (year - start) as f64 / (end - start) as f64;
+
+                    Some(from + (to - from) * progress)
+                }
+            }
+            Schedule::Values { values } => values.get(year as usize).or(values.last()).copied(),
+        }
+    }
+
+    /// The last year in which the value changes. The value stays the same from then on.
+    pub fn last_change(&self) -> u32 {
+        match self {
+            Schedule::Step { year, .. } => *year,
+            Schedule::Ramp { end, .. } => *end,
+            Schedule::Values { values } => values.len().saturating_sub(1) as u32,
+        }
+    }
+}
+
+/// A schedule for one parameter.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Policy {
+    pub parameter: Parameter,
+
+    #[serde(flatten)]
+    pub schedule: Schedule,
+}
+
+impl Policy {
+    pub fn new(parameter: Parameter, schedule: Schedule) -> Policy {
+        Policy {
+            parameter,
+            schedule,
+        }
+    }
+}
+
+/// Works out the settings in effect in a year. Policies are applied in order, so when two
+/// policies change the same parameter in the same year, the later one wins.
+pub fn effective_settings(settings: &Settings, policies: &[Policy], year: u32) -> Settings {
+    let mut effective = settings.clone();
+
+    for policy in policies {
+        if let Some(value) = policy.schedule.value_at(year) {
+            policy.parameter.set(&mut effective, value);
+        }
+    }
+
+    effective
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_schedules() {
+        let step = Schedule::Step {
+            year: 5,
+            value: 0.0,
+        };
+
+        assert_eq!(step.value_at(4), None);
+        assert_eq!(step.value_at(5), Some(0.0));
+
+        let ramp = Schedule::Ramp {
+            start: 0,
+            end: 10,
+            from: 0.5,
+            to: 0.4,
+        };
+
+        assert_eq!(ramp.value_at(0), Some(0.5));
+        assert!((ramp.value_at(5).unwrap() - 0.45).abs() < 1e-12);
+        assert_eq!(ramp.value_at(20), Some(0.4));
+
+        let values = Schedule::Values {
+            values: vec![2.1, 2.0],
+        };
+
+        assert_eq!(values.value_at(1), Some(2.0));
+        assert_eq!(values.value_at(9), Some(2.0));
+    }
+
+    #[test]
+    fn test_policies_from_json() {
+        let json = r#"[
+            { "parameter": "smokers", "kind": "step", "year": 5, "value": 0.0 },
+            { "parameter": "sugar", "kind": "ramp", "start": 0, "end": 10, "from": 0.5, "to": 0.4 }
+        ]"#;
+
+        let policies: Vec<Policy> = serde_json::from_str(json).unwrap();
+
+        let settings = Settings::new();
+
+        assert_eq!(effective_settings(&settings, &policies, 4).smokers, 0.2);
+        assert_eq!(effective_settings(&settings, &policies, 5).smokers, 0.0);
+        assert_eq!(effective_settings(&settings, &policies, 10).sugar, 0.4);
+
+        assert_eq!(
+            Parameter::from_name("hard_drugger"),
+            Some(Parameter::HardDrugger)
+        );
+        assert_eq!(Parameter::from_name("population"), None);
+    }
+}
@@ -0,0 +1,123 @@
+//! Scenario files for Beanreadings. A scenario is a set of settings saved as JSON, along with the
+//! seed, the version of Beanreadings that wrote it and some notes about what it is for, so that
+//! scenarios can be stored, shared and compared in git. For example:
+//!
+//! ```json
+//! {
+//!     "format_version": 1,
+//!     "crate_version": "0.1.0",
+//!     "seed": 42,
+//!     "notes": "A cigarette ban in year 5",
+//!     "settings": {
+//!         "years": 50,
+//!         "population": 10000,
+//!         "policies": [{ "parameter": "smokers", "kind": "step", "year": 5, "value": 0.0 }]
+//!     }
+//! }
+//! ```
+//!
+//! Settings that are left out keep their default values. The seed of the scenario always wins over
+//! a seed in the settings.
+
+use crate::types::{invalid_parameters, Settings, Simulation};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// The version of the scenario format written by this build. Files with a newer version are
+/// rejected, as they may have settings this build doesn't know about.
+pub const SCENARIO_FORMAT_VERSION: u32 = 1;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    pub format_version: u32,
+
+    pub crate_version: String, // the version of Beanreadings that wrote the file
+
+    pub seed: u64,
+
+    #[serde(default)]
+    pub notes: String,
+
+    pub settings: Settings,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Scenario {
+    /// Creates a scenario from settings, taking the seed from the settings.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(settings: Settings, notes: String) -> Scenario {
+        Scenario {
+            format_version: SCENARIO_FORMAT_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            seed: settings.seed,
+            notes,
+            settings,
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn from_json(json: &str) -> Result<Scenario, String> {
+        let mut scenario: Scenario = serde_json::from_str(json).map_err(invalid_parameters)?;
+
+        if scenario.format_version > SCENARIO_FORMAT_VERSION {
+            return Err(invalid_parameters(format!(
+                "scenario format {} is newer than {}, written by Beanreadings {}",
+                scenario.format_version, SCENARIO_FORMAT_VERSION, scenario.crate_version
+            )));
+        }
+
+        scenario.settings.seed = scenario.seed;
+
+        Ok(scenario)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// A simulation with the settings and seed of this scenario.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn simulation(&self) -> Simulation {
+        let mut settings = self.settings.clone();
+        settings.seed = self.seed;
+
+        Simulation::new(settings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scenario_round_trip() {
+        let mut settings = Settings::new();
+        settings.set_seed(42);
+        settings.set_smokers(0.3);
+        settings.add_step_policy("smokers", 5, 0.0).unwrap();
+
+        let scenario = Scenario::new(settings, "A cigarette ban in year 5".to_string());
+
+        assert_eq!(Scenario::from_json(&scenario.to_json()).unwrap(), scenario);
+
+        let json = r#"{
+            "format_version": 1,
+            "crate_version": "0.1.0",
+            "seed": 7,
+            "settings": { "years": 3, "seed": 1 }
+        }"#;
+
+        let scenario = Scenario::from_json(json).unwrap();
+
+        assert_eq!(scenario.settings.years, 3);
+        assert_eq!(scenario.settings.seed, 7);
+        assert_eq!(scenario.settings.population, Settings::new().population);
+
+        let newer = json.replace("\"format_version\": 1", "\"format_version\": 2");
+
+        assert!(Scenario::from_json(&newer).is_err());
+    }
+}
@@ -1,54 +1,831 @@
 //! Simulation logic for Beanreadings, simulating all of the beans in the world.
 
+use crate::age::AgeDistribution;
 use crate::bean::*;
+use crate::causes::CauseOfDeath;
+use crate::error::SimulationError;
+use crate::factors::Habit;
+use crate::rng::{seeded, SimRng};
 use crate::types::*;
-use crate::AgeGenerator;
+use crate::wealth::{quintiles, QUINTILES};
 
 use rand::Rng;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Simulation {
+    /// Runs the whole simulation. The settings are not checked, use `try_long` for settings that
+    /// come from users.
     pub fn long(&self) -> SimulationResult {
-        let mut population = self.settings.population;
+        let mut state = self.start();
 
-        let mut population_curve = Vec::new();
+        while !state.is_finished() {
+            state.step();
+        }
 
-        let mut age_gen = AgeGenerator::new(30.5); // median age
+        state.snapshot()
+    }
 
-        let mut rng = rand::thread_rng();
+    /// Starts a simulation that can be advanced one year at a time, instead of running it all at
+    /// once like `long`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn start(&self) -> SimulationState {
+        SimulationState::with_ages(self.settings.clone(), self.age_distribution().as_ref())
+    }
 
-        for _ in 0..self.settings.years {
-            // we simulate the population growth
+    /// Checks the settings, then runs the whole simulation.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn try_long(&self) -> Result<SimulationResult, SimulationError> {
+        self.settings.validate()?;
 
-            for _ in 0..population {
-                let gender = rng.gen_bool(0.49);
+        Ok(self.long())
+    }
 
-                if gender {
-                    // we have to generate this person a age
+    /// Checks the settings, then starts a simulation that can be stepped.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn try_start(&self) -> Result<SimulationState, SimulationError> {
+        self.settings.validate()?;
 
-                    let age = age_gen.generate_age();
+        Ok(self.start())
+    }
+}
 
-                    if age < 40 && age > 18 {
-                        let child_birth = rng.gen_bool(0.2); // they have a child
+/// A simulation in progress. `long` runs one of these to the end, but the web page steps through
+/// it a year at a time so that it can draw the graph as it goes and pause long runs.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct SimulationState {
+    settings: Settings,
 
-                        if child_birth {
-                            population += 1;
-                        }
-                    }
+    effective: Settings, // the settings in effect last year, after applying the policies
+
+    rng: SimRng, // the only source of randomness in the run
+
+    population: Vec<Bean>,
+
+    fertility: Vec<f64>, // age-specific fertility rates, worked out once from the settings
+
+    year: u32,
+
+    population_curve: Vec<u32>,
+
+    deaths_by_cause: Vec<u32>,
+
+    deaths_by_quintile: Vec<u32>,
+
+    exposure_by_quintile: Vec<u32>,
+
+    prevalence: Vec<f64>,
+
+    population_by_sex: Vec<u32>,
+
+    deaths_by_sex: Vec<u32>,
+
+    deaths_by_age: Vec<f64>,
+
+    exposure_by_age: Vec<f64>,
+}
+
+impl SimulationState {
+    /// Starts a simulation with the ages of the starting population drawn from `ages`.
+    pub fn with_ages(settings: Settings, ages: &dyn AgeDistribution) -> SimulationState {
+        let mut rng = seeded(settings.seed);
+
+        let effective = settings.at_year(0);
+
+        // the population is persistent, so every bean keeps its age and factors from one year to
+        // the next
+
+        let population: Vec<Bean> = (0..settings.population)
+            .map(|_| Bean::new(&effective, ages, &mut rng))
+            .collect();
+
+        SimulationState {
+            fertility: effective.fertility_rates(),
+            effective,
+            settings,
+            rng,
+            population,
+            year: 0,
+            population_curve: Vec::new(),
+            deaths_by_cause: Vec::new(),
+            deaths_by_quintile: Vec::new(),
+            exposure_by_quintile: Vec::new(),
+            prevalence: Vec::new(),
+            population_by_sex: Vec::new(),
+            deaths_by_sex: Vec::new(),
+            deaths_by_age: Vec::new(),
+            exposure_by_age: Vec::new(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl SimulationState {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(settings: Settings) -> SimulationState {
+        let ages = settings.age_distribution();
+
+        SimulationState::with_ages(settings, ages.as_ref())
+    }
+
+    /// Simulates one more year, and returns the population at the end of it. Stepping past
+    /// `settings.years` is allowed, and just keeps the simulation going.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn step(&mut self) -> u32 {
+        let rng = &mut self.rng;
+
+        // policies can change the settings from one year to the next, and the beans follow along
+
+        let effective = self.settings.at_year(self.year);
+
+        if effective != self.effective {
+            for bean in &mut self.population {
+                bean.follow_policy(&self.effective, &effective, rng);
+            }
+
+            self.fertility = effective.fertility_rates();
+            self.effective = effective;
+        }
+
+        let settings = &self.effective;
+
+        // then everybody has a chance to start, quit or relapse on their habits. Without habit
+        // transitions, beans born in the simulation pick theirs up when they come of age
+
+        match settings.habit_transitions() {
+            Some(transitions) => {
+                for bean in &mut self.population {
+                    bean.change_habits(transitions, rng);
+                }
+            }
+            None => {
+                for bean in &mut self.population {
+                    bean.come_of_age(settings, rng);
                 }
             }
+        }
+
+        // births come from the women in the population, at the rate for their age
+
+        let mut births = 0;
+
+        for bean in &self.population {
+            if bean.get_sex() != Sex::Female {
+                continue;
+            }
+
+            let rate = self.fertility.get(bean.get_age() as usize).copied();
+
+            if rng.gen_bool(rate.unwrap_or(0.0)) {
+                births += if rng.gen_bool(settings.twin_rate) {
+                    2
+                } else {
+                    1
+                };
+            }
+        }
+
+        // wealth quintiles are worked out from everybody alive at the start of the year
+
+        let wealths: Vec<f64> = self
+            .population
+            .iter()
+            .map(|bean| bean.get_factors().wealth.value)
+            .collect();
+
+        let quintiles = quintiles(&wealths);
+
+        let mut exposure = [0; QUINTILES];
+
+        for &quintile in &quintiles {
+            exposure[quintile] += 1;
+        }
+
+        // and by age and sex, for life expectancy. Nobody is older than max_age + 1, as that is
+        // when everyone dies of old age
+
+        let ages = settings.max_age as usize + 2;
+        let at =
+            |bean: &Bean| bean.get_sex().index() * ages + (bean.get_age() as usize).min(ages - 1);
+
+        let mut exposure_by_age = vec![0.0; Sex::ALL.len() * ages];
+
+        for bean in &self.population {
+            exposure_by_age[at(bean)] += 1.0;
+        }
+
+        let mut deaths = [0; CauseOfDeath::COUNT];
+        let mut deaths_by_sex = [0; Sex::ALL.len() * CauseOfDeath::COUNT];
+        let mut deaths_by_quintile = [0; QUINTILES];
+        let mut deaths_by_age = vec![0.0; Sex::ALL.len() * ages];
 
-            for _ in 0..population {
-                let bean = Bean::new(&self.settings, &mut age_gen);
-                if bean.dies(&self.settings) {
-                    population -= 1;
+        let mut quintile = quintiles.into_iter();
+
+        self.population.retain(|bean| {
+            let quintile = quintile.next().unwrap_or(0);
+
+            match bean.cause_of_death(settings, rng) {
+                Some(cause) => {
+                    deaths[cause.index()] += 1;
+                    deaths_by_sex[bean.get_sex().index() * CauseOfDeath::COUNT + cause.index()] +=
+                        1;
+                    deaths_by_quintile[quintile] += 1;
+                    deaths_by_age[at(bean)] += 1.0;
+                    false
                 }
+                None => true,
+            }
+        });
+
+        self.deaths_by_cause.extend(deaths);
+        self.deaths_by_sex.extend(deaths_by_sex);
+        self.deaths_by_quintile.extend(deaths_by_quintile);
+        self.exposure_by_quintile.extend(exposure);
+        self.deaths_by_age.extend(deaths_by_age);
+        self.exposure_by_age.extend(exposure_by_age);
+
+        // everybody who survived the year gets a year older, and the newborns join at age 0
+
+        for bean in &mut self.population {
+            bean.grow_older();
+        }
+
+        self.population
+            .extend((0..births).map(|_| Bean::newborn(settings, rng)));
+
+        self.year += 1;
+
+        let population = self.population.len() as u32;
+
+        self.population_curve.push(population);
+
+        let mut habits = [0; Habit::COUNT];
+        let mut by_sex = [0; Sex::ALL.len()];
+
+        for bean in &self.population {
+            by_sex[bean.get_sex().index()] += 1;
+
+            let factors = bean.get_factors();
+
+            for habit in Habit::ALL {
+                habits[habit.index()] += factors.has(habit) as u32;
+            }
+        }
+
+        self.prevalence
+            .extend(habits.map(|count| count as f64 / population.max(1) as f64));
+        self.population_by_sex.extend(by_sex);
+
+        population
+    }
+
+    /// Simulates up to `years` more years, stopping early once the run is finished. Returns the
+    /// population at the end.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn step_many(&mut self, years: u32) -> u32 {
+        for _ in 0..years {
+            if self.is_finished() {
+                break;
+            }
+
+            self.step();
+        }
+
+        self.get_population()
+    }
+
+    /// How many years have been simulated so far.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn current_year(&self) -> u32 {
+        self.year
+    }
+
+    /// Whether every year in `settings.years` has been simulated.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn is_finished(&self) -> bool {
+        self.year >= self.settings.years
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_population(&self) -> u32 {
+        self.population.len() as u32
+    }
+
+    /// The results so far, as if the simulation had been set to run for `current_year` years.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn snapshot(&self) -> SimulationResult {
+        SimulationResult {
+            population: self.population.len() as u32,
+            population_curve: self.population_curve.clone(),
+            deaths_by_cause: self.deaths_by_cause.clone(),
+            deaths_by_quintile: self.deaths_by_quintile.clone(),
+            exposure_by_quintile: self.exposure_by_quintile.clone(),
+            prevalence: self.prevalence.clone(),
+            population_by_sex: self.population_by_sex.clone(),
+            deaths_by_sex: self.deaths_by_sex.clone(),
+            deaths_by_age: self.deaths_by_age.clone(),
+            exposure_by_age: self.exposure_by_age.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::age::{AgeGenerator, LegacyAgeGenerator, PopulationPyramid, PyramidBand};
+    use crate::exposure::Exposures;
+    use crate::transitions::HabitTransitions;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_starting_ages() {
+        let mut settings = Settings::new();
+        settings.set_seed(18);
+        settings.set_age_pyramid(
+            PopulationPyramid::new(vec![PyramidBand::by_sex(20, 29, 1.0, 0.0)]).unwrap(),
+        );
+
+        let state = Simulation::new(settings.clone()).start();
+
+        assert!(state
+            .population
+            .iter()
+            .all(|bean| { (20..=29).contains(&bean.get_age()) && bean.get_sex() == Sex::Female }));
+
+        // a generator given to the simulation wins over the settings
+
+        let ages = Arc::new(LegacyAgeGenerator::new(60.0));
+        let state = Simulation::with_age_distribution(settings.clone(), ages).start();
+
+        assert!(state.population.iter().any(|bean| bean.get_age() > 29));
+
+        settings.set_max_age(25);
+
+        assert_eq!(settings.errors()[0].field, "age_pyramid");
+    }
+
+    #[test]
+    fn test_age_generators_keep_to_max_age() {
+        let mut settings = Settings::new();
+        settings.set_seed(18);
+        settings.set_max_age(60);
+
+        // nobody starts past the oldest age, where they would all die of old age in the first year
+
+        let mut simulation = Simulation::new(settings.clone());
+        simulation.set_age_generator(AgeGenerator::new(50.0));
+
+        let state = simulation.start();
+
+        assert!(state.population.iter().all(|bean| bean.get_age() <= 60));
+        assert!(state.population.iter().any(|bean| bean.get_age() > 50));
+
+        simulation.set_legacy_age_generator(LegacyAgeGenerator::new(50.0));
+
+        let state = simulation.start();
+
+        assert!(state.population.iter().all(|bean| bean.get_age() <= 60));
+    }
+
+    #[test]
+    fn test_beans_grow_older() {
+        let mut settings = Settings::new();
+        settings.set_seed(1);
+
+        let mut state = Simulation::new(settings).start();
+        let before = state.population.clone();
+
+        state.step();
+
+        // the survivors keep their order and the newborns come after them, so every bean is
+        // either one from before, a year older and otherwise the same, or a newborn
+
+        let deaths: u32 = state.deaths_by_cause.iter().sum();
+        let mut earlier = before.iter();
+        let mut survivors = 0;
+
+        for bean in &state.population {
+            let found = earlier.by_ref().find(|earlier| {
+                let mut older = (*earlier).clone();
+                older.grow_older();
+
+                bean.get_age() == earlier.get_age() + 1
+                    && bean.get_sex() == earlier.get_sex()
+                    && bean.get_factors() == older.get_factors()
+            });
+
+            match found {
+                Some(_) => survivors += 1,
+                None => assert_eq!(bean.get_age(), 0),
+            }
+        }
+
+        assert!(survivors > 0);
+        assert_eq!(survivors + deaths as usize, before.len());
+    }
+
+    #[test]
+    fn test_invalid_settings_are_rejected() {
+        let mut settings = Settings::new();
+        settings.set_smokers(1.5);
+        settings.set_fertility_rate(f64::NAN);
+
+        let error = Simulation::new(settings.clone()).try_long().unwrap_err();
+
+        assert_eq!(error.field, "smokers");
+        assert_eq!(error.code, ErrorCode::InvalidParameters);
+        assert_eq!(settings.errors().len(), 2);
+
+        // policies can make settings invalid later on
+
+        let mut settings = Settings::new();
+        settings.add_step_policy("drinkers", 3, 2.0).unwrap();
+
+        let errors = settings.errors();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "drinkers");
+        assert!(errors[0].reason.ends_with("in year 3"));
+
+        assert!(Simulation::new(Settings::new()).try_start().is_ok());
+    }
+
+    #[test]
+    fn test_same_seed_same_result() {
+        let mut settings = Settings::new();
+        settings.set_seed(7);
+
+        let first = Simulation::new(settings.clone()).long();
+        let second = Simulation::new(settings.clone()).long();
+
+        assert_eq!(first, second);
+
+        settings.set_seed(8);
+
+        assert_ne!(first, Simulation::new(settings).long());
+    }
+
+    #[test]
+    fn test_deaths_add_up() {
+        let mut settings = Settings::new();
+        settings.set_seed(3);
+
+        let result = Simulation::new(settings.clone()).long();
+
+        let deaths: u32 = result.get_total_deaths().iter().sum();
+
+        let births = result.get_population() + deaths - settings.population;
+
+        // every death is attributed to exactly one cause
+
+        let by_cause: u32 = CauseOfDeath::ALL
+            .iter()
+            .map(|cause| result.get_deaths_by_cause(*cause).iter().sum::<u32>())
+            .sum();
+
+        assert_eq!(deaths, by_cause);
+        assert!(births > 0);
+        assert_eq!(result.get_deaths_in_year(0).len(), CauseOfDeath::COUNT);
+    }
+
+    #[test]
+    fn test_no_births_without_fertility() {
+        let mut settings = Settings::new();
+        settings.set_seed(5);
+        settings.set_fertility_rate(0.0);
+
+        let result = Simulation::new(settings.clone()).long();
+
+        let deaths: u32 = result.get_total_deaths().iter().sum();
+
+        assert_eq!(result.get_population() + deaths, settings.population);
+    }
+
+    #[test]
+    fn test_stepping_matches_long() {
+        let mut settings = Settings::new();
+        settings.set_seed(9);
+
+        let simulation = Simulation::new(settings.clone());
+
+        let mut state = simulation.start();
+
+        state.step_many(4);
+
+        assert_eq!(state.current_year(), 4);
+        assert_eq!(state.snapshot().get_population_curve().len(), 4);
+
+        state.step_many(100); // stops at settings.years
+
+        assert!(state.is_finished());
+        assert_eq!(state.snapshot(), simulation.long());
+    }
+
+    #[test]
+    fn test_policy_changes_population() {
+        let mut settings = Settings::new();
+        settings.set_seed(13);
+        settings.set_years(20);
+
+        let mut banned = settings.clone();
+        banned
+            .set_policies_json(
+                r#"[{ "parameter": "fertility_rate", "kind": "step", "year": 5, "value": 0.0 }]"#,
+            )
+            .unwrap();
+
+        let base = Simulation::new(settings).long();
+        let result = Simulation::new(banned).long();
+
+        // the first five years are exactly the same, as policies don't draw any random numbers
+        // until they start
+
+        assert_eq!(
+            base.get_population_curve()[..5],
+            result.get_population_curve()[..5]
+        );
+        assert!(result.get_population() < base.get_population());
+    }
+
+    #[test]
+    fn test_poorest_die_most() {
+        let mut settings = Settings::new();
+        settings.set_seed(17);
+        settings.set_population(5000);
+
+        let result = Simulation::new(settings).long();
+
+        let poorest: f64 = result.get_death_rate_by_quintile(0).iter().sum();
+        let richest: f64 = result.get_death_rate_by_quintile(4).iter().sum();
+
+        assert!(poorest > richest);
+    }
+
+    /// A 60 year old man who smokes and the same man who never did, both without a dose, so the
+    /// table's risks apply as they are.
+    fn smoker_and_never(settings: &Settings) -> (Bean, Bean) {
+        let mut factors = Bean::with_age(settings, 60, Sex::Male, &mut seeded(1)).get_factors();
+        factors.clear_habits();
+        factors.exposures = Exposures::default();
+
+        let never = Bean::from_parts(60, Sex::Male, factors.clone());
+
+        factors.smokes_cigarettes = true;
+
+        (Bean::from_parts(60, Sex::Male, factors), never)
+    }
+
+    #[test]
+    fn test_transitions_quit_and_start() {
+        let mut settings = Settings::new();
+        settings.set_seed(21);
+
+        let base = Simulation::new(settings.clone()).long();
+
+        settings
+            .set_habit_transitions_json(
+                r#"{
+                    "habits": {
+                        "smokes_cigarettes": [{ "from": 0, "quit": 1.0 }],
+                        "alcoholism": [{ "from": 18, "start": 1.0 }]
+                    }
+                }"#,
+            )
+            .unwrap();
+
+        let result = Simulation::new(settings).long();
+
+        // everybody quits smoking in the first year, and every adult starts drinking
+
+        assert!(base.get_prevalence(Habit::SmokesCigarettes)[0] > 0.1);
+        assert_eq!(result.get_prevalence(Habit::SmokesCigarettes)[0], 0.0);
+        assert!(result.get_prevalence(Habit::Alcoholism)[0] > 0.6);
+    }
+
+    #[test]
+    fn test_transitions_relapse() {
+        let settings = Settings::new();
+
+        let (mut smoker, mut never) = smoker_and_never(&settings);
+
+        let quitting = HabitTransitions::from_json(
+            r#"{ "habits": { "smokes_cigarettes": [{ "from": 0, "quit": 1.0 }] } }"#,
+        )
+        .unwrap();
+
+        let relapsing = HabitTransitions::from_json(
+            r#"{ "habits": { "smokes_cigarettes": [{ "from": 0, "relapse": 1.0 }] } }"#,
+        )
+        .unwrap();
+
+        smoker.change_habits(&quitting, &mut seeded(2));
+
+        assert!(!smoker.get_factors().smokes_cigarettes);
+
+        // only beans that gave the habit up relapse, beans that never had it don't start
+
+        smoker.change_habits(&relapsing, &mut seeded(3));
+        never.change_habits(&relapsing, &mut seeded(3));
+
+        assert!(smoker.get_factors().smokes_cigarettes);
+        assert_eq!(smoker.years_since_quitting(Habit::SmokesCigarettes), None);
+        assert!(!never.get_factors().smokes_cigarettes);
+    }
+
+    #[test]
+    fn test_prevalence_series() {
+        let mut settings = Settings::new();
+        settings.set_seed(25);
+
+        let result = Simulation::new(settings.clone()).long();
+        let series = result.series();
+
+        for habit in Habit::ALL {
+            let name = format!("prevalence.{}", habit.key());
+            let (_, values) = series.iter().find(|(key, _)| *key == name).unwrap();
+
+            assert_eq!(values.len(), settings.years as usize);
+            assert!(values.iter().all(|share| (0.0..=1.0).contains(share)));
+        }
+    }
+
+    #[test]
+    fn test_former_risk_decays() {
+        let mut settings = Settings::new();
+        settings
+            .set_habit_transitions_json(r#"{ "former_risk_half_life": 5 }"#)
+            .unwrap();
+
+        let (mut smoker, mut never) = smoker_and_never(&settings);
+
+        let quitting = HabitTransitions::from_json(
+            r#"{ "habits": { "smokes_cigarettes": [{ "from": 0, "quit": 1.0 }] } }"#,
+        )
+        .unwrap();
+
+        let mut former = smoker.clone();
+        former.change_habits(&quitting, &mut seeded(2));
+
+        // former smokers keep the extra risk of smoking at first, and lose three quarters of it
+        // in two half-lives
+
+        assert_eq!(
+            former.years_since_quitting(Habit::SmokesCigarettes),
+            Some(0)
+        );
+        assert_eq!(former.risks(&settings), smoker.risks(&settings));
+
+        for bean in [&mut never, &mut smoker, &mut former] {
+            for _ in 0..10 {
+                bean.grow_older();
+            }
+        }
+
+        let respiratory = CauseOfDeath::RespiratoryDisease.index();
+        let relative =
+            |bean: &Bean| bean.risks(&settings)[respiratory] / never.risks(&settings)[respiratory];
+
+        assert!((relative(&smoker) - 2.5).abs() < 1e-9);
+        assert!((relative(&former) - 1.375).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cohort_rejects_transitions() {
+        let mut settings = Settings::new();
+        settings
+            .set_habit_transitions_json(
+                r#"{ "habits": { "smokes_cigarettes": [{ "from": 0, "quit": 1.0 }] } }"#,
+            )
+            .unwrap();
+
+        let error = Simulation::new(settings).try_cohort().unwrap_err();
+
+        assert_eq!(error.field, "habit_transitions");
+    }
+
+    #[test]
+    fn test_habits_are_picked_up() {
+        let mut settings = Settings::new();
+        settings.set_seed(7);
+        settings.set_population(3000);
+        settings.set_years(150);
+
+        // without habit transitions, beans born in the simulation pick up their habits when they
+        // come of age, so every habit stays about as common as it started out
+
+        let result = Simulation::new(settings.clone()).long();
+
+        for habit in Habit::ALL {
+            if habit.rate(&settings).is_none() {
+                continue;
             }
 
-            population_curve.push(population);
+            let prevalence = result.get_prevalence(habit);
+            let first = prevalence[0];
+
+            assert!(first > 0.0);
+
+            for year in (25..150).step_by(25) {
+                let ratio = prevalence[year] / first;
+
+                assert!(
+                    (0.5..1.5).contains(&ratio),
+                    "{} in year {}: {} against {}",
+                    habit.key(),
+                    year,
+                    prevalence[year],
+                    first
+                );
+            }
+        }
+
+        // and only adults have them
+
+        let state = Simulation::new(settings).start();
+
+        assert!(state
+            .population
+            .iter()
+            .filter(|bean| bean.get_age() < ADULT_AGE)
+            .all(|bean| Habit::ALL
+                .iter()
+                .all(|&habit| !bean.get_factors().has(habit))));
+    }
+
+    #[test]
+    fn test_policy_quitters_keep_risk() {
+        let mut settings = Settings::new();
+        settings.set_seed(22);
+
+        let mut banned = settings.clone();
+        banned.set_smokers(0.0);
+
+        // beans that quit because of a ban carry the smoker's risk at first, and lose half of it
+        // in the default ten years without any habit transitions
+
+        let (smoker, mut never) = smoker_and_never(&settings);
+        let mut former = smoker.clone();
+
+        former.follow_policy(&settings, &banned, &mut seeded(2));
+
+        assert_eq!(
+            former.years_since_quitting(Habit::SmokesCigarettes),
+            Some(0)
+        );
+        assert_eq!(former.risks(&settings), smoker.risks(&settings));
+
+        for _ in 0..10 {
+            former.grow_older();
+            never.grow_older();
         }
 
-        SimulationResult::new(population, population_curve)
+        let respiratory = CauseOfDeath::RespiratoryDisease.index();
+        let relative = former.risks(&settings)[respiratory] / never.risks(&settings)[respiratory];
+
+        assert!((relative - 1.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_results_by_sex() {
+        let mut settings = Settings::new();
+        settings.set_seed(24);
+
+        let result = Simulation::new(settings.clone()).long();
+
+        // both sexes add up to everyone
+
+        let women = result.get_population_curve_by_sex(Sex::Female);
+        let men = result.get_population_curve_by_sex(Sex::Male);
+
+        for year in 0..result.get_population_curve().len() {
+            assert_eq!(women[year] + men[year], result.get_population_curve()[year]);
+
+            let deaths: u32 = Sex::ALL
+                .iter()
+                .map(|&sex| result.get_total_deaths_by_sex(sex)[year])
+                .sum();
+
+            assert_eq!(deaths, result.get_total_deaths()[year]);
+        }
+
+        assert_eq!(
+            result.get_deaths_by_cause_and_sex(CauseOfDeath::Cancer, Sex::Female)[0]
+                + result.get_deaths_by_cause_and_sex(CauseOfDeath::Cancer, Sex::Male)[0],
+            result.get_deaths_by_cause(CauseOfDeath::Cancer)[0]
+        );
+
+        // men die younger, from the table and from having more habits, in both engines
+
+        settings.set_population(1_000_000);
+        settings.set_years(30);
+
+        let cohort = Simulation::new(settings).cohort();
+
+        let female = cohort.get_life_expectancy(Sex::Female);
+        let male = cohort.get_life_expectancy(Sex::Male);
+
+        assert!(female > male);
+        assert!(male > 40.0 && female < 100.0);
     }
 }
@@ -0,0 +1,650 @@
+//! Training data and training for the BRNN surrogate. The network in `brnn.rs` is only as good as
+//! the runs it was trained on, so this module makes those runs and trains new networks from them:
+//!
+//! 1. Settings are sampled over their ranges with a Latin hypercube, so that every part of the
+//!    range of every setting is covered, even with few samples.
+//! 2. The simulation is run for every sample, giving a dataset of inputs and final populations.
+//! 3. A multilayer perceptron is trained on part of the dataset with Adam, and exported as a
+//!    normal `Brnn`, with the scaling of the inputs and the output folded into its weights.
+//! 4. The other part of the dataset is held out, and used to make a table of errors, so that
+//!    networks can be compared as the model changes.
+//!
+//! The `train_brnn` binary runs all of this from the command line.
+
+use crate::brnn::{apply_features, features, Brnn, Layer, FEATURES, FEATURE_NAMES};
+use crate::rng::{derive_seed, seeded};
+use crate::types::{Settings, Simulation};
+use rand::Rng;
+use std::fmt::Write as _;
+
+/// The range each input of the network is sampled from, in the order of `FEATURE_NAMES`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleRanges {
+    pub ranges: [(f64, f64); FEATURES],
+}
+
+impl Default for SampleRanges {
+    /// Every setting over the range it is valid in, except for the population and the number of
+    /// years, which are kept small enough for thousands of runs.
+    fn default() -> Self {
+        SampleRanges {
+            ranges: [
+                (1.0, 50.0),     // years
+                (100.0, 5000.0), // population
+                (60.0, 120.0),   // max_age
+                (0.0, 1.0),      // smokers
+                (0.0, 1.0),      // weed_smokers
+                (0.0, 1.0),      // vapers
+                (0.0, 1.0),      // drinkers
+                (0.0, 1.0),      // hard_drugger
+                (0.0, 1.5),      // sugar
+                (0.0, 1.5),      // salt
+                (0.0, 1.5),      // fat
+                (0.0, 1.5),      // vitamins
+                (-1.0, 1.0),     // wealth_factor
+                (0.0, 4.0),      // fertility_rate
+                (0.9, 1.2),      // sex_ratio_at_birth
+                (0.0, 0.05),     // twin_rate
+            ],
+        }
+    }
+}
+
+/// Samples points with a Latin hypercube: the range of every input is cut into `samples` equal
+/// strata, and every stratum is used exactly once.
+pub fn latin_hypercube<R: Rng + ?Sized>(
+    samples: usize,
+    ranges: &SampleRanges,
+    rng: &mut R,
+) -> Vec<[f64; FEATURES]> {
+    let mut points = vec![[0.0; FEATURES]; samples];
+
+    for (feature, &(min, max)) in ranges.ranges.iter().enumerate() {
+        let mut strata: Vec<usize> = (0..samples).collect();
+
+        // Fisher-Yates, with u32 ranges so the shuffle is the same on every platform
+
+        for i in (1..samples).rev() {
+            let j = rng.gen_range(0..=i as u32) as usize;
+            strata.swap(i, j);
+        }
+
+        for (point, stratum) in points.iter_mut().zip(strata) {
+            let position = (stratum as f64 + rng.gen_range(0.0..1.0)) / samples as f64;
+
+            point[feature] = min + (max - min) * position;
+        }
+    }
+
+    points
+}
+
+/// Inputs of the network, and the population the simulation ended with for each of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Dataset {
+    pub features: Vec<[f32; FEATURES]>,
+    pub labels: Vec<f32>,
+}
+
+impl Dataset {
+    /// Runs the simulation for `samples` settings sampled from `ranges`. Everything that isn't an
+    /// input of the network comes from `base`, and every run gets its own seed derived from
+    /// `base.seed`.
+    pub fn generate(samples: usize, ranges: &SampleRanges, base: &Settings) -> Dataset {
+        let mut rng = seeded(derive_seed(base.seed, 0));
+
+        let mut dataset = Dataset::default();
+
+        for (index, point) in latin_hypercube(samples, ranges, &mut rng)
+            .iter()
+            .enumerate()
+        {
+            let mut settings = base.clone();
+            apply_features(&mut settings, point);
+            settings.seed = derive_seed(base.seed, index as u64 + 1);
+
+            let result = Simulation::new(settings.clone()).long();
+
+            dataset.push(features(&settings), result.get_population() as f32);
+        }
+
+        dataset
+    }
+
+    pub fn push(&mut self, features: [f32; FEATURES], label: f32) {
+        self.features.push(features);
+        self.labels.push(label);
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Shuffles the dataset and splits it into a training set and a held out test set with
+    /// `test_fraction` of the samples.
+    pub fn split(&self, test_fraction: f64, seed: u64) -> (Dataset, Dataset) {
+        let mut rng = seeded(seed);
+        let mut order: Vec<usize> = (0..self.len()).collect();
+
+        for i in (1..order.len()).rev() {
+            let j = rng.gen_range(0..=i as u32) as usize;
+            order.swap(i, j);
+        }
+
+        let test_size = (self.len() as f64 * test_fraction.clamp(0.0, 1.0)).round() as usize;
+
+        let mut train = Dataset::default();
+        let mut test = Dataset::default();
+
+        for (position, index) in order.into_iter().enumerate() {
+            let set = if position < test_size {
+                &mut test
+            } else {
+                &mut train
+            };
+
+            set.push(self.features[index], self.labels[index]);
+        }
+
+        (train, test)
+    }
+
+    /// The dataset as CSV, with a column for every input and a `population` column.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{},population\n", FEATURE_NAMES.join(","));
+
+        for (features, label) in self.features.iter().zip(&self.labels) {
+            for value in features {
+                let _ = write!(csv, "{},", value);
+            }
+
+            let _ = writeln!(csv, "{}", label);
+        }
+
+        csv
+    }
+
+    pub fn from_csv(csv: &str) -> Result<Dataset, String> {
+        let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+
+        let header = lines.next().ok_or("the dataset is empty")?;
+
+        if header.split(',').count() != FEATURES + 1 {
+            return Err(format!("the dataset should have {} columns", FEATURES + 1));
+        }
+
+        let mut dataset = Dataset::default();
+
+        for (row, line) in lines.enumerate() {
+            let values: Vec<f32> = line
+                .split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<Result<_, _>>()
+                .map_err(|error| format!("row {}: {}", row + 1, error))?;
+
+            if values.len() != FEATURES + 1 {
+                return Err(format!("row {} has {} columns", row + 1, values.len()));
+            }
+
+            let mut features = [0.0; FEATURES];
+            features.copy_from_slice(&values[..FEATURES]);
+
+            dataset.push(features, values[FEATURES]);
+        }
+
+        Ok(dataset)
+    }
+}
+
+/// How to train a network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainingOptions {
+    pub hidden: Vec<usize>, // the number of neurons in each hidden layer
+
+    pub epochs: u32,
+
+    pub batch_size: usize,
+
+    pub learning_rate: f64,
+
+    pub seed: u64, // for the starting weights and the order of the batches
+}
+
+impl Default for TrainingOptions {
+    fn default() -> Self {
+        TrainingOptions {
+            hidden: vec![48; 3],
+            epochs: 300,
+            batch_size: 32,
+            learning_rate: 0.003,
+            seed: 0,
+        }
+    }
+}
+
+/// A layer while it is being trained, in f64, with the Adam moments of every parameter.
+struct Dense {
+    inputs: usize,
+    outputs: usize,
+    weights: Vec<f64>,
+    bias: Vec<f64>,
+    relu: bool,
+    moments: [Vec<f64>; 4], // first and second moments of the weights, then of the bias
+}
+
+impl Dense {
+    fn new<R: Rng + ?Sized>(inputs: usize, outputs: usize, relu: bool, rng: &mut R) -> Dense {
+        // He initialisation, which suits ReLU layers
+
+        let limit = (6.0 / inputs as f64).sqrt();
+
+        Dense {
+            inputs,
+            outputs,
+            weights: (0..inputs * outputs)
+                .map(|_| rng.gen_range(-limit..limit))
+                .collect(),
+            bias: vec![0.0; outputs],
+            relu,
+            moments: [
+                vec![0.0; inputs * outputs],
+                vec![0.0; inputs * outputs],
+                vec![0.0; outputs],
+                vec![0.0; outputs],
+            ],
+        }
+    }
+
+    fn forward(&self, input: &[f64]) -> Vec<f64> {
+        self.weights
+            .chunks(self.inputs)
+            .zip(&self.bias)
+            .map(|(row, bias)| {
+                let sum = row.iter().zip(input).map(|(w, x)| w * x).sum::<f64>() + bias;
+
+                if self.relu {
+                    sum.max(0.0)
+                } else {
+                    sum
+                }
+            })
+            .collect()
+    }
+}
+
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+fn adam(
+    parameters: &mut [f64],
+    gradients: &[f64],
+    first: &mut [f64],
+    second: &mut [f64],
+    rate: f64,
+    step: i32,
+) {
+    let correction1 = 1.0 - BETA1.powi(step);
+    let correction2 = 1.0 - BETA2.powi(step);
+
+    for (((parameter, gradient), m), v) in parameters
+        .iter_mut()
+        .zip(gradients)
+        .zip(first.iter_mut())
+        .zip(second.iter_mut())
+    {
+        *m = BETA1 * *m + (1.0 - BETA1) * gradient;
+        *v = BETA2 * *v + (1.0 - BETA2) * gradient * gradient;
+
+        *parameter -= rate * (*m / correction1) / ((*v / correction2).sqrt() + EPSILON);
+    }
+}
+
+/// The mean and standard deviation of some values. A spread of zero is replaced by 1, so that
+/// constant inputs don't divide by zero.
+fn scale(values: impl Iterator<Item = f64> + Clone) -> (f64, f64) {
+    let count = values.clone().count().max(1) as f64;
+    let mean = values.clone().sum::<f64>() / count;
+    let variance = values.map(|value| (value - mean).powi(2)).sum::<f64>() / count;
+
+    let spread = variance.sqrt();
+
+    (mean, if spread > 0.0 { spread } else { 1.0 })
+}
+
+/// Trains a network to predict the labels of a dataset with mean squared error. Inputs and
+/// labels are standardised while training, and the scaling is folded back into the weights of
+/// the first and last layers, so the result takes raw inputs like any other `Brnn`.
+pub fn train(data: &Dataset, options: &TrainingOptions) -> Result<Brnn, String> {
+    if data.is_empty() {
+        return Err("there is nothing to train on".to_string());
+    }
+
+    let mut rng = seeded(options.seed);
+
+    let input_scales: Vec<(f64, f64)> = (0..FEATURES)
+        .map(|feature| scale(data.features.iter().map(move |row| row[feature] as f64)))
+        .collect();
+
+    let (label_mean, label_spread) = scale(data.labels.iter().map(|&label| label as f64));
+
+    let inputs: Vec<Vec<f64>> = data
+        .features
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(&input_scales)
+                .map(|(&value, (mean, spread))| (value as f64 - mean) / spread)
+                .collect()
+        })
+        .collect();
+
+    let labels: Vec<f64> = data
+        .labels
+        .iter()
+        .map(|&label| (label as f64 - label_mean) / label_spread)
+        .collect();
+
+    let mut sizes = vec![FEATURES];
+    sizes.extend(&options.hidden);
+    sizes.push(1);
+
+    let mut layers: Vec<Dense> = sizes
+        .windows(2)
+        .enumerate()
+        .map(|(index, size)| Dense::new(size[0], size[1], index + 2 < sizes.len(), &mut rng))
+        .collect();
+
+    let batch_size = options.batch_size.max(1);
+    let mut order: Vec<usize> = (0..data.len()).collect();
+    let mut step = 0;
+
+    for _ in 0..options.epochs {
+        for i in (1..order.len()).rev() {
+            let j = rng.gen_range(0..=i as u32) as usize;
+            order.swap(i, j);
+        }
+
+        for batch in order.chunks(batch_size) {
+            let mut weight_gradients: Vec<Vec<f64>> = layers
+                .iter()
+                .map(|layer| vec![0.0; layer.weights.len()])
+                .collect();
+            let mut bias_gradients: Vec<Vec<f64>> = layers
+                .iter()
+                .map(|layer| vec![0.0; layer.outputs])
+                .collect();
+
+            for &sample in batch {
+                let mut activations = vec![inputs[sample].clone()];
+
+                for layer in &layers {
+                    let next = layer.forward(activations.last().expect("starts with the input"));
+                    activations.push(next);
+                }
+
+                let prediction = activations[layers.len()][0];
+                let mut delta = vec![2.0 * (prediction - labels[sample]) / batch.len() as f64];
+
+                for (index, layer) in layers.iter().enumerate().rev() {
+                    if layer.relu {
+                        for (d, &a) in delta.iter_mut().zip(&activations[index + 1]) {
+                            if a <= 0.0 {
+                                *d = 0.0;
+                            }
+                        }
+                    }
+
+                    let input = &activations[index];
+                    let mut previous = vec![0.0; layer.inputs];
+
+                    for (output, &d) in delta.iter().enumerate() {
+                        let row = output * layer.inputs;
+
+                        for (j, &x) in input.iter().enumerate() {
+                            weight_gradients[index][row + j] += d * x;
+                            previous[j] += layer.weights[row + j] * d;
+                        }
+
+                        bias_gradients[index][output] += d;
+                    }
+
+                    delta = previous;
+                }
+            }
+
+            step += 1;
+
+            for ((layer, weights), bias) in layers
+                .iter_mut()
+                .zip(&weight_gradients)
+                .zip(&bias_gradients)
+            {
+                let [m_w, v_w, m_b, v_b] = &mut layer.moments;
+
+                adam(
+                    &mut layer.weights,
+                    weights,
+                    m_w,
+                    v_w,
+                    options.learning_rate,
+                    step,
+                );
+                adam(&mut layer.bias, bias, m_b, v_b, options.learning_rate, step);
+            }
+        }
+    }
+
+    // fold the input scaling into the first layer, and the label scaling into the last
+
+    let first = &mut layers[0];
+
+    for row in first
+        .weights
+        .chunks_mut(FEATURES)
+        .zip(first.bias.iter_mut())
+    {
+        let (weights, bias) = row;
+
+        for (weight, (mean, spread)) in weights.iter_mut().zip(&input_scales) {
+            *weight /= spread;
+            *bias -= *weight * mean;
+        }
+    }
+
+    let last = layers.last_mut().expect("there is always an output layer");
+
+    for weight in &mut last.weights {
+        *weight *= label_spread;
+    }
+
+    for bias in &mut last.bias {
+        *bias = *bias * label_spread + label_mean;
+    }
+
+    Brnn::new(
+        layers
+            .into_iter()
+            .map(|layer| Layer {
+                inputs: layer.inputs,
+                outputs: layer.outputs,
+                weights: layer.weights.iter().map(|&w| w as f32).collect(),
+                bias: layer.bias.iter().map(|&b| b as f32).collect(),
+                relu: layer.relu,
+            })
+            .collect(),
+    )
+}
+
+/// How well a network predicts a held out dataset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorRow {
+    pub model: String,
+    pub samples: usize,
+    pub mse: f64,
+    pub rmse: f64,
+    pub mae: f64,
+    pub mean_relative_error: f64, // the error as a share of the actual population
+    pub r_squared: f64,
+}
+
+/// Measures a network against a dataset it wasn't trained on.
+pub fn evaluate(model: &str, network: &Brnn, data: &Dataset) -> ErrorRow {
+    let count = data.len().max(1) as f64;
+
+    let errors: Vec<(f64, f64)> = data
+        .features
+        .iter()
+        .zip(&data.labels)
+        .map(|(features, &label)| {
+            let prediction = network.forward(features.to_vec())[0] as f64;
+
+            (prediction - label as f64, label as f64)
+        })
+        .collect();
+
+    let mse = errors.iter().map(|(error, _)| error * error).sum::<f64>() / count;
+    let mae = errors.iter().map(|(error, _)| error.abs()).sum::<f64>() / count;
+
+    let mean_relative_error = errors
+        .iter()
+        .map(|(error, label)| error.abs() / label.abs().max(1.0))
+        .sum::<f64>()
+        / count;
+
+    let label_mean = errors.iter().map(|(_, label)| label).sum::<f64>() / count;
+    let total = errors
+        .iter()
+        .map(|(_, label)| (label - label_mean).powi(2))
+        .sum::<f64>();
+
+    let r_squared = if total > 0.0 {
+        1.0 - mse * count / total
+    } else {
+        0.0
+    };
+
+    ErrorRow {
+        model: model.to_string(),
+        samples: data.len(),
+        mse,
+        rmse: mse.sqrt(),
+        mae,
+        mean_relative_error,
+        r_squared,
+    }
+}
+
+/// Errors of several networks on the same held out data.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorTable {
+    pub rows: Vec<ErrorRow>,
+}
+
+impl ErrorTable {
+    /// The table in Markdown, to paste into a pull request or keep next to the model.
+    pub fn to_markdown(&self) -> String {
+        let mut table = String::from(
+            "| model | samples | MSE | RMSE | MAE | mean relative error | R² |\n\
+             |-------|---------|-----|------|-----|---------------------|----|\n",
+        );
+
+        for row in &self.rows {
+            let _ = writeln!(
+                table,
+                "| {} | {} | {:.1} | {:.1} | {:.1} | {:.1}% | {:.3} |",
+                row.model,
+                row.samples,
+                row.mse,
+                row.rmse,
+                row.mae,
+                row.mean_relative_error * 100.0,
+                row.r_squared
+            );
+        }
+
+        table
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_latin_hypercube() {
+        let ranges = SampleRanges::default();
+        let points = latin_hypercube(10, &ranges, &mut seeded(1));
+
+        // every tenth of the range of every input is used exactly once
+
+        for (feature, &(min, max)) in ranges.ranges.iter().enumerate() {
+            let mut strata: Vec<usize> = points
+                .iter()
+                .map(|point| ((point[feature] - min) / (max - min) * 10.0) as usize)
+                .collect();
+
+            strata.sort();
+
+            assert_eq!(strata, (0..10).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_training_learns() {
+        // a made up target, so the test doesn't have to run thousands of simulations
+
+        let mut rng = seeded(2);
+        let mut data = Dataset::default();
+
+        for point in latin_hypercube(400, &SampleRanges::default(), &mut rng) {
+            let label = point[1] * (1.0 + 0.01 * point[0]) - 500.0 * point[3];
+
+            data.push(point.map(|value| value as f32), label as f32);
+        }
+
+        let (train_set, test_set) = data.split(0.25, 3);
+
+        let options = TrainingOptions {
+            hidden: vec![16, 16],
+            epochs: 200,
+            ..TrainingOptions::default()
+        };
+
+        let network = train(&train_set, &options).unwrap();
+
+        let row = evaluate("trained", &network, &test_set);
+
+        assert_eq!(row.samples, 100);
+        assert!(row.r_squared > 0.95, "{:?}", row);
+
+        // the exported network survives a round trip through JSON
+
+        assert_eq!(Brnn::from_json(&network.to_json()).unwrap(), network);
+
+        assert_eq!(Dataset::from_csv(&test_set.to_csv()).unwrap(), test_set);
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut ranges = SampleRanges::default();
+        ranges.ranges[0] = (1.0, 3.0);
+        ranges.ranges[1] = (10.0, 50.0);
+
+        let mut base = Settings::new();
+        base.set_seed(5);
+
+        let data = Dataset::generate(4, &ranges, &base);
+
+        assert_eq!(data.len(), 4);
+        assert!(data
+            .features
+            .iter()
+            .all(|row| (1.0..=3.0).contains(&row[0])));
+        assert_eq!(data, Dataset::generate(4, &ranges, &base));
+    }
+}
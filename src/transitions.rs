@@ -0,0 +1,268 @@
+//! Habit transitions for Beanreadings. Without them, a bean keeps the habits it started with for
+//! its whole life (apart from following policies). With them, every year each bean can start a
+//! habit it never had, quit one it has, or relapse into one it gave up, at rates that depend on
+//! its age.
+//!
+//! The rates are given per habit, in bands of ages, as yearly chances. Ages outside every band
+//! have no transitions. Transitions are JSON, for example:
+//!
+//! ```json
+//! {
+//!     "former_risk_half_life": 10,
+//!     "habits": {
+//!         "smokes_cigarettes": [
+//!             { "from": 12, "to": 24, "start": 0.02, "quit": 0.03, "relapse": 0.2 },
+//!             { "from": 25, "start": 0.002, "quit": 0.05, "relapse": 0.05 }
+//!         ]
+//!     }
+//! }
+//! ```
+//!
+//! The habits have the same names as in `Factors` and hazard tables. Binge drinking can only be
+//! started by drinkers, and stops when they stop drinking.
+//!
+//! Beans that gave up a habit keep part of its extra risk: the relative risk from the hazard
+//! table above 1 halves every `former_risk_half_life` years after quitting. This goes for beans
+//! that quit because of a policy too, and uses `DEFAULT_FORMER_RISK_HALF_LIFE` when there are no
+//! transitions.
+
+use crate::factors::Habit;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How many years it takes for half of the extra risk of a habit to go away after quitting, when
+/// it isn't given.
+pub const DEFAULT_FORMER_RISK_HALF_LIFE: f64 = 10.0;
+
+/// The yearly chances of changing a habit for a range of ages, `to` included. A band without
+/// `to` goes on forever.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransitionBand {
+    pub from: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<u32>,
+
+    #[serde(default)]
+    pub start: f64, // chance of picking up the habit, for beans that never had it
+
+    #[serde(default)]
+    pub quit: f64, // chance of giving it up
+
+    #[serde(default)]
+    pub relapse: f64, // chance of picking it up again, for beans that gave it up
+}
+
+impl TransitionBand {
+    pub fn contains(&self, age: u32) -> bool {
+        age >= self.from && self.to.is_none_or(|to| age <= to)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransitionsFile {
+    #[serde(default = "default_half_life")]
+    former_risk_half_life: f64,
+
+    #[serde(default)]
+    habits: BTreeMap<String, Vec<TransitionBand>>,
+}
+
+fn default_half_life() -> f64 {
+    DEFAULT_FORMER_RISK_HALF_LIFE
+}
+
+/// Validated habit transitions, see the module docs for the format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "TransitionsFile", into = "TransitionsFile")]
+pub struct HabitTransitions {
+    former_risk_half_life: f64,
+
+    bands: [Vec<TransitionBand>; Habit::COUNT], // by `Habit::index`
+}
+
+impl HabitTransitions {
+    /// Transitions without any bands, so nobody changes their habits until some are added.
+    pub fn new(former_risk_half_life: f64) -> Result<HabitTransitions, String> {
+        HabitTransitions::try_from(TransitionsFile {
+            former_risk_half_life,
+            habits: BTreeMap::new(),
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<HabitTransitions, String> {
+        serde_json::from_str(json).map_err(|error| error.to_string())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Replaces the bands of a habit, checking them like the ones read from JSON.
+    pub fn set_bands(&mut self, habit: Habit, bands: Vec<TransitionBand>) -> Result<(), String> {
+        check_bands(habit, &bands)?;
+
+        self.bands[habit.index()] = bands;
+
+        Ok(())
+    }
+
+    pub fn bands(&self, habit: Habit) -> &[TransitionBand] {
+        &self.bands[habit.index()]
+    }
+
+    /// The band with the chances of changing a habit at an age, if there is one.
+    pub fn band(&self, habit: Habit, age: u32) -> Option<&TransitionBand> {
+        self.bands(habit).iter().find(|band| band.contains(age))
+    }
+
+    pub fn former_risk_half_life(&self) -> f64 {
+        self.former_risk_half_life
+    }
+
+    /// The share of a habit's extra risk that is left `years` after giving it up.
+    pub fn remaining_risk(&self, years: u32) -> f64 {
+        remaining_risk(self.former_risk_half_life, years)
+    }
+}
+
+/// The share of a habit's extra risk that is left `years` after giving it up, when half of it goes
+/// away every `half_life` years.
+pub fn remaining_risk(half_life: f64, years: u32) -> f64 {
+    0.5f64.powf(years as f64 / half_life)
+}
+
+fn check_bands(habit: Habit, bands: &[TransitionBand]) -> Result<(), String> {
+    for (index, band) in bands.iter().enumerate() {
+        if band.to.is_some_and(|to| to < band.from) {
+            return Err(format!(
+                "band {} of {} starts after it ends",
+                index + 1,
+                habit.key()
+            ));
+        }
+
+        if index > 0 && bands[index - 1].to.is_none_or(|to| band.from <= to) {
+            return Err(format!(
+                "band {} of {} overlaps the band before it, bands must be in order of age",
+                index + 1,
+                habit.key()
+            ));
+        }
+
+        for (name, chance) in [
+            ("start", band.start),
+            ("quit", band.quit),
+            ("relapse", band.relapse),
+        ] {
+            if !(0.0..=1.0).contains(&chance) {
+                return Err(format!(
+                    "{} chance in band {} of {} should be between 0 and 1, got {}",
+                    name,
+                    index + 1,
+                    habit.key(),
+                    chance
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl TryFrom<TransitionsFile> for HabitTransitions {
+    type Error = String;
+
+    fn try_from(file: TransitionsFile) -> Result<Self, Self::Error> {
+        if !file.former_risk_half_life.is_finite() || file.former_risk_half_life <= 0.0 {
+            return Err(format!(
+                "former_risk_half_life should be above 0, got {}",
+                file.former_risk_half_life
+            ));
+        }
+
+        let mut bands: [Vec<TransitionBand>; Habit::COUNT] = Default::default();
+
+        for (key, habit_bands) in file.habits {
+            let habit = Habit::from_key(&key).ok_or_else(|| format!("unknown habit {}", key))?;
+
+            check_bands(habit, &habit_bands)?;
+
+            bands[habit.index()] = habit_bands;
+        }
+
+        Ok(HabitTransitions {
+            former_risk_half_life: file.former_risk_half_life,
+            bands,
+        })
+    }
+}
+
+impl From<HabitTransitions> for TransitionsFile {
+    fn from(transitions: HabitTransitions) -> Self {
+        let habits = Habit::ALL
+            .into_iter()
+            .zip(transitions.bands)
+            .filter(|(_, bands)| !bands.is_empty())
+            .map(|(habit, bands)| (habit.key().to_string(), bands))
+            .collect();
+
+        TransitionsFile {
+            former_risk_half_life: transitions.former_risk_half_life,
+            habits,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_transitions_json() {
+        let transitions = HabitTransitions::from_json(
+            r#"{
+                "habits": {
+                    "smokes_cigarettes": [
+                        { "from": 12, "to": 24, "start": 0.02, "quit": 0.03, "relapse": 0.2 },
+                        { "from": 25, "quit": 0.05 }
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            transitions.former_risk_half_life(),
+            DEFAULT_FORMER_RISK_HALF_LIFE
+        );
+        assert_eq!(
+            transitions.band(Habit::SmokesCigarettes, 30).unwrap().quit,
+            0.05
+        );
+        assert!(transitions.band(Habit::SmokesCigarettes, 5).is_none());
+        assert!(transitions.bands(Habit::SmokesVape).is_empty());
+
+        assert_eq!(transitions.remaining_risk(0), 1.0);
+        assert!((transitions.remaining_risk(20) - 0.25).abs() < 1e-12);
+
+        let json = transitions.to_json();
+
+        assert_eq!(HabitTransitions::from_json(&json).unwrap(), transitions);
+
+        // bad habits, overlapping bands, chances above 1 and half lives of 0 are all rejected
+
+        assert!(HabitTransitions::from_json(r#"{ "habits": { "knitting": [] } }"#).is_err());
+        assert!(HabitTransitions::from_json(
+            r#"{ "habits": { "smokes_weed": [{ "from": 10 }, { "from": 20 }] } }"#
+        )
+        .is_err());
+        assert!(HabitTransitions::from_json(
+            r#"{ "habits": { "smokes_weed": [{ "from": 10, "quit": 1.5 }] } }"#
+        )
+        .is_err());
+        assert!(HabitTransitions::from_json(r#"{ "former_risk_half_life": 0 }"#).is_err());
+    }
+}
@@ -1,54 +1,377 @@
 //! Here in Beanreadings, we define crucial types to the simulation.
 //! These include types such as the results, the simulation parameters and the species.
 
+use crate::age::{
+    AgeDistribution, AgeGenerator, EmpiricalAgeDistribution, LegacyAgeGenerator, PopulationPyramid,
+};
+use crate::bean::Sex;
+use crate::behaviors::HabitCorrelations;
+use crate::causes::CauseOfDeath;
+use crate::error::SimulationError;
+use crate::exposure::Doses;
+use crate::factors::{Habit, HabitSexRatios};
+use crate::fertility::FertilitySchedule;
+use crate::hazard::HazardTable;
+use crate::life_table::LifeTable;
+use crate::policy::*;
+use crate::transitions::HabitTransitions;
+use crate::wealth::{WealthEffects, QUINTILES};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
-#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Debug, Clone)]
 pub struct Simulation {
     pub settings: Settings,
+
+    ages: Option<Arc<dyn AgeDistribution>>, // overrides the ages from the settings when set
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Simulation {
-    #[wasm_bindgen(constructor)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
     pub fn new(config: Settings) -> Simulation {
-        Simulation { settings: config }
+        Simulation {
+            settings: config,
+            ages: None,
+        }
+    }
+
+    /// Starts the population with ages from the custom generator, with a different median age.
+    /// None of the ages go above the `max_age` in the settings.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_age_generator(&mut self, generator: AgeGenerator) {
+        self.ages = Some(Arc::new(AgeGenerator::bounded(
+            generator.get_median_age(),
+            generator.get_max_age().min(self.settings.max_age),
+        )));
+    }
+
+    /// Starts the population with normally distributed ages, none of them above the `max_age` in
+    /// the settings.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_legacy_age_generator(&mut self, generator: LegacyAgeGenerator) {
+        self.ages = Some(Arc::new(LegacyAgeGenerator::bounded(
+            generator.median_age,
+            generator.max_age.min(self.settings.max_age),
+        )));
     }
     // we add other simulation related methods in src/simulation.rs and not here
 }
 
-#[wasm_bindgen]
-#[derive(Debug, Clone)]
+impl Simulation {
+    /// A simulation whose starting population gets its ages from any `AgeDistribution`.
+    pub fn with_age_distribution(config: Settings, ages: Arc<dyn AgeDistribution>) -> Simulation {
+        Simulation {
+            settings: config,
+            ages: Some(ages),
+        }
+    }
+
+    /// The same simulation, including its age distribution, with other settings.
+    pub fn with_settings(&self, settings: Settings) -> Simulation {
+        Simulation {
+            settings,
+            ages: self.ages.clone(),
+        }
+    }
+
+    /// Where the ages of the starting population come from: the distribution given to the
+    /// simulation, or else the one from the settings.
+    pub fn age_distribution(&self) -> Arc<dyn AgeDistribution> {
+        self.ages
+            .clone()
+            .unwrap_or_else(|| self.settings.age_distribution())
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct SimulationResult {
     pub population: u32,
-    population_curve: Vec<u32>,
+    pub(crate) population_curve: Vec<u32>,
+    pub(crate) deaths_by_cause: Vec<u32>, // CauseOfDeath::COUNT entries per year, one after the other
+    pub(crate) deaths_by_quintile: Vec<u32>, // QUINTILES entries per year, poorest first
+    pub(crate) exposure_by_quintile: Vec<u32>, // beans alive at the start of each year, by quintile
+    pub(crate) prevalence: Vec<f64>, // Habit::COUNT shares per year, of everyone alive at the end of it
+    pub(crate) population_by_sex: Vec<u32>, // women then men at the end of each year
+    pub(crate) deaths_by_sex: Vec<u32>, // CauseOfDeath::COUNT entries for women then men, per year
+    pub(crate) deaths_by_age: Vec<f64>, // deaths at every age up to max_age + 1, for women then men, per year, unrounded from the cohort engine
+    pub(crate) exposure_by_age: Vec<f64>, // beans alive at the start of each year, in the same order
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl SimulationResult {
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn get_population(&self) -> u32 {
         self.population
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn get_population_curve(&self) -> Vec<u32> {
         // it cant implement Copy because Vec is not Copy
         self.population_curve.clone()
     }
 
-    #[wasm_bindgen(constructor)]
-    pub fn new(population: u32, population_curve: Vec<u32>) -> SimulationResult {
+    /// How many beans died of a cause in each year of the simulation.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_deaths_by_cause(&self, cause: CauseOfDeath) -> Vec<u32> {
+        self.deaths_by_cause
+            .chunks(CauseOfDeath::COUNT)
+            .map(|year| year[cause.index()])
+            .collect()
+    }
+
+    /// How many beans died of each cause in a year, in the order of `cause_of_death_names`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_deaths_in_year(&self, year: usize) -> Vec<u32> {
+        self.deaths_by_cause
+            .chunks(CauseOfDeath::COUNT)
+            .nth(year)
+            .map(|deaths| deaths.to_vec())
+            .unwrap_or_default()
+    }
+
+    /// How many beans died of any cause in each year of the simulation.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_total_deaths(&self) -> Vec<u32> {
+        self.deaths_by_cause
+            .chunks(CauseOfDeath::COUNT)
+            .map(|year| year.iter().sum())
+            .collect()
+    }
+
+    /// How many beans of a sex were alive at the end of each year.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_population_curve_by_sex(&self, sex: Sex) -> Vec<u32> {
+        self.population_by_sex
+            .chunks(Sex::ALL.len())
+            .map(|year| year[sex.index()])
+            .collect()
+    }
+
+    /// How many beans of a sex died of a cause in each year.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_deaths_by_cause_and_sex(&self, cause: CauseOfDeath, sex: Sex) -> Vec<u32> {
+        self.deaths_by_sex
+            .chunks(Sex::ALL.len() * CauseOfDeath::COUNT)
+            .map(|year| year[sex.index() * CauseOfDeath::COUNT + cause.index()])
+            .collect()
+    }
+
+    /// How many beans of a sex died of any cause in each year.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_total_deaths_by_sex(&self, sex: Sex) -> Vec<u32> {
+        self.deaths_by_sex
+            .chunks(Sex::ALL.len() * CauseOfDeath::COUNT)
+            .map(|year| {
+                year.chunks(CauseOfDeath::COUNT)
+                    .nth(sex.index())
+                    .map_or(0, |deaths| deaths.iter().sum())
+            })
+            .collect()
+    }
+
+    /// The life table of a sex from the deaths and beans alive at each age over the whole
+    /// simulation, pooled together.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_life_table(&self, sex: Sex) -> LifeTable {
+        let ages = self.ages();
+
+        let mut deaths = vec![0.0; ages];
+        let mut exposure = vec![0.0; ages];
+
+        for year in 0..self.population_curve.len() {
+            let (year_deaths, year_exposure) = self.by_age(sex, year);
+
+            for age in 0..ages {
+                deaths[age] += year_deaths[age];
+                exposure[age] += year_exposure[age];
+            }
+        }
+
+        LifeTable::from_deaths(deaths, exposure)
+    }
+
+    /// The period life table of a sex in one year of the simulation.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_period_life_table(&self, sex: Sex, year: usize) -> LifeTable {
+        let (deaths, exposure) = self.by_age(sex, year);
+
+        LifeTable::from_deaths(deaths, exposure)
+    }
+
+    /// The cohort life table of a sex for the beans born in a year, following them as they get
+    /// older. Years before the simulation (for cohorts that were already alive at the start) or
+    /// after it use the chances of the first or the last year.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_cohort_life_table(&self, sex: Sex, born: i32) -> LifeTable {
+        let last = self.population_curve.len().saturating_sub(1) as i64;
+
+        let chances = (0..self.ages())
+            .map(|age| {
+                let year = (born as i64 + age as i64).clamp(0, last) as usize;
+
+                self.get_period_life_table(sex, year).get_qx()[age]
+            })
+            .collect();
+
+        LifeTable::new(chances)
+    }
+
+    /// The life expectancy at birth of a sex, from the life table of the whole simulation.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_life_expectancy(&self, sex: Sex) -> f64 {
+        self.get_life_table(sex).life_expectancy()
+    }
+
+    /// How many more years a bean of a sex and age can expect to live, from the life table of
+    /// the whole simulation.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_life_expectancy_at(&self, sex: Sex, age: u32) -> f64 {
+        self.get_life_table(sex).life_expectancy_at(age)
+    }
+
+    /// How many beans in a wealth quintile died in each year. Quintile 0 is the poorest fifth of
+    /// the population at the start of the year, and quintile 4 the richest.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_deaths_by_quintile(&self, quintile: usize) -> Vec<u32> {
+        self.deaths_by_quintile
+            .chunks(QUINTILES)
+            .map(|year| year.get(quintile).copied().unwrap_or(0))
+            .collect()
+    }
+
+    /// The share of a wealth quintile that died in each year.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_death_rate_by_quintile(&self, quintile: usize) -> Vec<f64> {
+        self.get_deaths_by_quintile(quintile)
+            .iter()
+            .zip(self.exposure_by_quintile.chunks(QUINTILES))
+            .map(|(&deaths, exposure)| match exposure.get(quintile) {
+                Some(&alive) if alive > 0 => deaths as f64 / alive as f64,
+                _ => 0.0,
+            })
+            .collect()
+    }
+
+    /// The share of the population with a habit at the end of each year. Nobody younger than
+    /// `ADULT_AGE` has any habits, so this is below the rate of adults in the settings.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_prevalence(&self, habit: Habit) -> Vec<f64> {
+        self.prevalence
+            .chunks(Habit::COUNT)
+            .map(|year| year[habit.index()])
+            .collect()
+    }
+
+    /// Names of every series in `series`, in the same order.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_series_names(&self) -> Vec<String> {
+        self.series().into_iter().map(|(name, _)| name).collect()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        population: u32,
+        population_curve: Vec<u32>,
+        deaths_by_cause: Vec<u32>,
+    ) -> SimulationResult {
         SimulationResult {
             population,
             population_curve,
+            deaths_by_cause,
+            ..Default::default()
         }
     }
 }
 
-#[wasm_bindgen]
-#[derive(Debug, Clone)]
+impl SimulationResult {
+    /// How many ages deaths and exposure are split into, from 0 up to `max_age + 1`.
+    fn ages(&self) -> usize {
+        let years = self.population_curve.len().max(1);
+
+        (self.deaths_by_age.len() / (years * Sex::ALL.len())).max(1)
+    }
+
+    /// The deaths and beans alive at each age for a sex in a year, or nothing for years that
+    /// weren't simulated.
+    fn by_age(&self, sex: Sex, year: usize) -> (Vec<f64>, Vec<f64>) {
+        let ages = self.ages();
+        let start = (year * Sex::ALL.len() + sex.index()) * ages;
+
+        let column = |counts: &[f64]| -> Vec<f64> {
+            (start..start + ages)
+                .map(|i| counts.get(i).copied().unwrap_or(0.0))
+                .collect()
+        };
+
+        (column(&self.deaths_by_age), column(&self.exposure_by_age))
+    }
+
+    /// Every per-year series tracked by the simulation, by name. Anything listed here is
+    /// summarised by ensemble runs, so new series only have to be added in one place.
+    pub fn series(&self) -> Vec<(String, Vec<f64>)> {
+        let mut series = vec![
+            (
+                "population".to_string(),
+                self.population_curve.iter().map(|&n| n as f64).collect(),
+            ),
+            (
+                "deaths".to_string(),
+                self.get_total_deaths().iter().map(|&n| n as f64).collect(),
+            ),
+        ];
+
+        for cause in CauseOfDeath::ALL {
+            series.push((
+                format!("deaths.{}", cause.key()),
+                self.get_deaths_by_cause(cause)
+                    .iter()
+                    .map(|&n| n as f64)
+                    .collect(),
+            ));
+        }
+
+        for quintile in 0..QUINTILES {
+            series.push((
+                format!("death_rate.quintile_{}", quintile + 1),
+                self.get_death_rate_by_quintile(quintile),
+            ));
+        }
+
+        for sex in Sex::ALL {
+            series.push((
+                format!("population.{}", sex.key()),
+                self.get_population_curve_by_sex(sex)
+                    .iter()
+                    .map(|&n| n as f64)
+                    .collect(),
+            ));
+            series.push((
+                format!("deaths.{}", sex.key()),
+                self.get_total_deaths_by_sex(sex)
+                    .iter()
+                    .map(|&n| n as f64)
+                    .collect(),
+            ));
+        }
+
+        for habit in Habit::ALL {
+            series.push((
+                format!("prevalence.{}", habit.key()),
+                self.get_prevalence(habit),
+            ));
+        }
+
+        series
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorCode {
     InvalidParameters,
     Overflow,
@@ -65,22 +388,27 @@ impl From<ErrorCode> for String {
     }
 }
 
-#[wasm_bindgen]
-#[derive(Clone, Debug, Copy)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)] // missing settings keep their default values
 pub struct Settings {
     pub years: u32,      // how many years the simulation will run for
     pub population: u32, // base population
     pub max_age: u32, // Longevity Escape Velocity could come in the future, especially with AGI on
     // the horizon, so this should be a customizable parameter
-    pub smokers: f64, // percentage of population who smokes
+    pub smokers: f64, // percentage of adults who smoke
 
-    pub weed_smokers: f64, // percentage of population who smokes weed
+    pub weed_smokers: f64, // percentage of adults who smoke weed
 
-    pub vapers: f64, // percentage of population who vapes
+    pub vapers: f64, // percentage of adults who vape
 
-    pub drinkers: f64, // percentage of population who drinks alcohol
+    pub drinkers: f64, // percentage of adults who drink alcohol
 
-    pub hard_drugger: f64, // percentage of population who uses hard drugs
+    pub hard_drugger: f64, // percentage of adults who use hard drugs
+
+    pub binge_share: f64, // share of drinkers who binge drink
+
+    pub habit_sex_ratios: HabitSexRatios, // how much more common each habit is among men
 
     pub sugar: f64, // % of the RDI of sugar in the diet
 
@@ -91,11 +419,41 @@ pub struct Settings {
     pub vitamins: f64, // % of the RDI of vitamins in the diet (generally below 100%)
 
     pub wealth_factor: f64, // What the discreptancy in wealth is between the richest and the poorest. Right now it is a percentage, percentage of the upper class people - the lower class, so generally a negative factor
+
+    pub seed: u64, // seed for the random number generator, the same seed always gives the same result
+
+    pub fertility_rate: f64, // total fertility rate, the average number of children per woman
+
+    pub sex_ratio_at_birth: f64, // boys born for every girl, around 1.05 in most countries
+
+    pub twin_rate: f64, // chance of a birth being twins
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fertility_schedule: Option<FertilitySchedule>, // the built in schedule is used when this is None
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    policies: Vec<Policy>, // changes to the settings over the course of the simulation
+
+    pub wealth_effects: WealthEffects, // how much wealth changes the risk of dying, see wealth.rs
+
+    pub doses: Doses, // how much of their habits beans use, and what it does, see exposure.rs
+
+    #[serde(rename = "hazard_table", skip_serializing_if = "Option::is_none")]
+    hazards: Option<Arc<HazardTable>>, // the built in table is used when this is None
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age_pyramid: Option<Arc<PopulationPyramid>>, // the custom age generator is used when this is None
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    habit_correlations: Option<Arc<HabitCorrelations>>, // the built in correlations are used when this is None
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    habit_transitions: Option<Arc<HabitTransitions>>, // habits never change on their own when this is None
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Settings {
-    #[wasm_bindgen(constructor)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
     pub fn new() -> Self {
         Settings {
             years: 10,
@@ -106,78 +464,585 @@ impl Settings {
             vapers: 0.1,
             drinkers: 0.2,
             hard_drugger: 0.05,
+            binge_share: 1.0 / 3.0, // one third of drinkers are binge drinkers
+            habit_sex_ratios: HabitSexRatios::new(),
             sugar: 0.5,
             salt: 0.5,
             fat: 0.5,
             vitamins: 0.5,
             wealth_factor: -0.1, // 20% of the population is upper class, 50% is middle class, 30% is lower class
+            seed: rand::random(), // a fresh seed every time, unless one is set
+            fertility_rate: 2.13, // replaces the population with the default hazard table
+            sex_ratio_at_birth: 1.05,
+            twin_rate: 0.012,
+            fertility_schedule: None,
+            policies: Vec::new(),
+            wealth_effects: WealthEffects::new(),
+            doses: Doses::new(),
+            hazards: None,
+            age_pyramid: None,
+            habit_correlations: None,
+            habit_transitions: None,
         }
     }
 
+    /// Reads settings from JSON, with the same field names as here. Settings that are left out
+    /// keep their default values.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn from_json(json: &str) -> Result<Settings, String> {
+        serde_json::from_str(json).map_err(invalid_parameters)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
     // since its WASM we have to add methods to set the values
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_population(&mut self, population: u32) {
         self.population = population;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_max_age(&mut self, max_age: u32) {
         self.max_age = max_age;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_smokers(&mut self, smokers: f64) {
         self.smokers = smokers;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_weed_smokers(&mut self, weed_smokers: f64) {
         self.weed_smokers = weed_smokers;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_vapers(&mut self, vapers: f64) {
         self.vapers = vapers;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_drinkers(&mut self, drinkers: f64) {
         self.drinkers = drinkers;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_binge_share(&mut self, binge_share: f64) {
+        self.binge_share = binge_share;
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_sugar(&mut self, sugar: f64) {
         self.sugar = sugar;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_salt(&mut self, salt: f64) {
         self.salt = salt;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_fat(&mut self, fat: f64) {
         self.fat = fat;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_vitamins(&mut self, vitamins: f64) {
         self.vitamins = vitamins;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_wealth_factor(&mut self, wealth_factor: f64) {
         self.wealth_factor = wealth_factor;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_hard_drugger(&mut self, hard_drugger: f64) {
         self.hard_drugger = hard_drugger;
     }
 
-    #[wasm_bindgen]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
     pub fn set_years(&mut self, years: u32) {
         self.years = years;
     }
+
+    /// Sets the seed of the run. From JavaScript this is a `BigInt`, see `rng.rs` for seeds that
+    /// go through JSON.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_fertility_rate(&mut self, fertility_rate: f64) {
+        self.fertility_rate = fertility_rate;
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_sex_ratio_at_birth(&mut self, sex_ratio_at_birth: f64) {
+        self.sex_ratio_at_birth = sex_ratio_at_birth;
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_twin_rate(&mut self, twin_rate: f64) {
+        self.twin_rate = twin_rate;
+    }
+
+    /// Replaces the built in age-specific fertility schedule with a custom one.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_fertility_schedule(&mut self, schedule: FertilitySchedule) {
+        self.fertility_schedule = Some(schedule);
+    }
+
+    /// The chance of a woman giving birth at each age, from the schedule and the total fertility
+    /// rate.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn fertility_rates(&self) -> Vec<f64> {
+        match &self.fertility_schedule {
+            Some(schedule) => schedule.rates(self.fertility_rate),
+            None => FertilitySchedule::default().rates(self.fertility_rate),
+        }
+    }
+
+    /// The chance of a newborn being a boy.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn male_birth_probability(&self) -> f64 {
+        self.sex_ratio_at_birth / (1.0 + self.sex_ratio_at_birth)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_wealth_effects(&mut self, wealth_effects: WealthEffects) {
+        self.wealth_effects = wealth_effects;
+    }
+
+    /// Replaces every policy with the ones in a JSON list, see `policy.rs` for the format.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_policies_json(&mut self, json: &str) -> Result<(), String> {
+        self.policies = serde_json::from_str(json).map_err(invalid_parameters)?;
+
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_policies_json(&self) -> String {
+        serde_json::to_string(&self.policies).unwrap_or_default()
+    }
+
+    /// Changes `parameter` to `value` from `year` onwards.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn add_step_policy(
+        &mut self,
+        parameter: &str,
+        year: u32,
+        value: f64,
+    ) -> Result<(), String> {
+        self.add_schedule(parameter, Schedule::Step { year, value })
+    }
+
+    /// Moves `parameter` from `from` to `to` between the years `start` and `end`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn add_ramp_policy(
+        &mut self,
+        parameter: &str,
+        start: u32,
+        end: u32,
+        from: f64,
+        to: f64,
+    ) -> Result<(), String> {
+        self.add_schedule(
+            parameter,
+            Schedule::Ramp {
+                start,
+                end,
+                from,
+                to,
+            },
+        )
+    }
+
+    /// Gives `parameter` its own value in every year.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn add_values_policy(&mut self, parameter: &str, values: Vec<f64>) -> Result<(), String> {
+        self.add_schedule(parameter, Schedule::Values { values })
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_policies(&mut self) {
+        self.policies.clear();
+    }
+
+    /// Replaces the risks of dying with a hazard table, see `hazard.rs` for the format.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_hazard_table_json(&mut self, json: &str) -> Result<(), String> {
+        self.hazards = Some(Arc::new(HazardTable::from_json(json)?));
+
+        Ok(())
+    }
+
+    /// The hazard table in use, as JSON. A good starting point for writing a new one.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_hazard_table_json(&self) -> String {
+        self.hazards().to_json()
+    }
+
+    /// Goes back to the built in hazard table.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn reset_hazard_table(&mut self) {
+        self.hazards = None;
+    }
+
+    /// Starts the population from a population pyramid, see `age.rs` for the format.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_age_pyramid_json(&mut self, json: &str) -> Result<(), String> {
+        self.age_pyramid = Some(Arc::new(
+            PopulationPyramid::from_json(json).map_err(invalid_parameters)?,
+        ));
+
+        Ok(())
+    }
+
+    /// The population pyramid in use as JSON, or an empty string without one.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_age_pyramid_json(&self) -> String {
+        self.age_pyramid
+            .as_ref()
+            .map(|pyramid| pyramid.to_json())
+            .unwrap_or_default()
+    }
+
+    /// Goes back to generating the ages of the starting population.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_age_pyramid(&mut self) {
+        self.age_pyramid = None;
+    }
+
+    /// Replaces the correlations between habits, see `behaviors.rs` for the format.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_habit_correlations_json(&mut self, json: &str) -> Result<(), String> {
+        self.habit_correlations = Some(Arc::new(
+            HabitCorrelations::from_json(json).map_err(invalid_parameters)?,
+        ));
+
+        Ok(())
+    }
+
+    /// The correlations between habits in use, as JSON.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_habit_correlations_json(&self) -> String {
+        self.habit_correlations().to_json()
+    }
+
+    /// Goes back to the built in correlations between habits.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn reset_habit_correlations(&mut self) {
+        self.habit_correlations = None;
+    }
+
+    /// Lets beans start, quit and relapse on their habits every year, see `transitions.rs` for
+    /// the format.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_habit_transitions_json(&mut self, json: &str) -> Result<(), String> {
+        self.habit_transitions = Some(Arc::new(
+            HabitTransitions::from_json(json).map_err(invalid_parameters)?,
+        ));
+
+        Ok(())
+    }
+
+    /// The habit transitions in use as JSON, or an empty string without them.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_habit_transitions_json(&self) -> String {
+        self.habit_transitions
+            .as_ref()
+            .map(|transitions| transitions.to_json())
+            .unwrap_or_default()
+    }
+
+    /// Goes back to beans keeping the habits they start with.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_habit_transitions(&mut self) {
+        self.habit_transitions = None;
+    }
+
+    /// Checks the settings, and fails with the first setting that is wrong. Simulations started
+    /// with `try_long` or `try_start` do this first.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn validate(&self) -> Result<(), SimulationError> {
+        match self.errors().into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// A message for every setting that is wrong, so that they can all be shown at once.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn validation_errors(&self) -> Vec<String> {
+        self.errors()
+            .iter()
+            .map(|error| error.to_string())
+            .collect()
+    }
+}
+
+impl Settings {
+    pub fn add_policy(&mut self, policy: Policy) {
+        self.policies.push(policy);
+    }
+
+    pub fn policies(&self) -> &[Policy] {
+        &self.policies
+    }
+
+    pub fn set_hazard_table(&mut self, table: HazardTable) {
+        self.hazards = Some(Arc::new(table));
+    }
+
+    pub fn set_age_pyramid(&mut self, pyramid: PopulationPyramid) {
+        self.age_pyramid = Some(Arc::new(pyramid));
+    }
+
+    pub fn age_pyramid(&self) -> Option<&PopulationPyramid> {
+        self.age_pyramid.as_deref()
+    }
+
+    pub fn set_habit_correlations(&mut self, correlations: HabitCorrelations) {
+        self.habit_correlations = Some(Arc::new(correlations));
+    }
+
+    /// The correlations between habits that beans are drawn with.
+    pub fn habit_correlations(&self) -> &HabitCorrelations {
+        self.habit_correlations
+            .as_deref()
+            .unwrap_or(HabitCorrelations::builtin())
+    }
+
+    pub fn set_habit_transitions(&mut self, transitions: HabitTransitions) {
+        self.habit_transitions = Some(Arc::new(transitions));
+    }
+
+    pub fn habit_transitions(&self) -> Option<&HabitTransitions> {
+        self.habit_transitions.as_deref()
+    }
+
+    /// The ages the starting population is drawn from: the population pyramid if there is one,
+    /// and the custom age generator otherwise.
+    pub fn age_distribution(&self) -> Arc<dyn AgeDistribution> {
+        match &self.age_pyramid {
+            Some(pyramid) => Arc::new(EmpiricalAgeDistribution::new((**pyramid).clone())),
+            None => Arc::new(AgeGenerator::bounded(30.5, self.max_age)), // median age
+        }
+    }
+
+    /// The hazard table the risks of dying come from.
+    pub fn hazards(&self) -> &HazardTable {
+        self.hazards.as_deref().unwrap_or(HazardTable::builtin())
+    }
+
+    /// Everything wrong with the settings, at most one error per setting. Policies are checked
+    /// too, in every year up to the last one they change something in.
+    pub fn errors(&self) -> Vec<SimulationError> {
+        let mut errors = self.field_errors();
+
+        let last_change = self
+            .policies
+            .iter()
+            .map(|policy| policy.schedule.last_change())
+            .max();
+
+        if let Some(last_change) = last_change {
+            for year in 0..=last_change.min(self.years) {
+                for error in self.at_year(year).field_errors() {
+                    if errors.iter().all(|existing| existing.field != error.field) {
+                        errors.push(SimulationError {
+                            reason: format!("{} in year {}", error.reason, year),
+                            ..error
+                        });
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+
+    fn field_errors(&self) -> Vec<SimulationError> {
+        let mut errors = Vec::new();
+
+        if self.max_age > MAX_AGE {
+            errors.push(SimulationError::new(
+                "max_age",
+                format!("should be at most {}, got {}", MAX_AGE, self.max_age),
+                ErrorCode::Overflow,
+            ));
+        }
+
+        if let Some(pyramid) = &self.age_pyramid {
+            if pyramid.oldest() > self.max_age {
+                errors.push(SimulationError::invalid(
+                    "age_pyramid",
+                    format!(
+                        "goes up to age {}, past max_age of {}",
+                        pyramid.oldest(),
+                        self.max_age
+                    ),
+                ));
+            }
+        }
+
+        let probabilities = [
+            ("smokers", self.smokers),
+            ("weed_smokers", self.weed_smokers),
+            ("vapers", self.vapers),
+            ("drinkers", self.drinkers),
+            ("hard_drugger", self.hard_drugger),
+            ("binge_share", self.binge_share),
+            ("twin_rate", self.twin_rate),
+            (
+                "wealth_effects.minimum_care_access",
+                self.wealth_effects.minimum_care_access,
+            ),
+            (
+                "wealth_effects.treatment_effect",
+                self.wealth_effects.treatment_effect,
+            ),
+        ];
+
+        for (field, value) in probabilities {
+            if !(0.0..=1.0).contains(&value) {
+                errors.push(SimulationError::invalid(
+                    field,
+                    format!("should be between 0 and 1, got {}", value),
+                ));
+            }
+        }
+
+        let amounts = [
+            ("sugar", self.sugar),
+            ("salt", self.salt),
+            ("fat", self.fat),
+            ("vitamins", self.vitamins),
+            ("fertility_rate", self.fertility_rate),
+            ("wealth_effects.infection", self.wealth_effects.infection),
+            (
+                "wealth_effects.malnutrition",
+                self.wealth_effects.malnutrition,
+            ),
+            (
+                "wealth_effects.cardiovascular_disease",
+                self.wealth_effects.cardiovascular_disease,
+            ),
+        ];
+
+        for (field, value) in amounts {
+            if !value.is_finite() || value < 0.0 {
+                errors.push(SimulationError::invalid(
+                    field,
+                    format!("should be a positive number, got {}", value),
+                ));
+            }
+        }
+
+        for habit in Habit::ALL {
+            let Some(ratio) = self.habit_sex_ratios.get(habit) else {
+                continue;
+            };
+
+            if !ratio.is_finite() || ratio <= 0.0 {
+                errors.push(SimulationError::invalid(
+                    format!("habit_sex_ratios.{}", habit.key()),
+                    format!("should be above 0, got {}", ratio),
+                ));
+            }
+        }
+
+        // doses can be spread out or not at all, but the rest has to be above 0
+
+        for (field, value) in self.doses.fields() {
+            if !value.is_finite() || value < 0.0 || (value == 0.0 && !field.ends_with(".spread")) {
+                errors.push(SimulationError::invalid(
+                    field,
+                    format!("should be above 0, got {}", value),
+                ));
+            }
+        }
+
+        if !self.wealth_factor.is_finite() {
+            errors.push(SimulationError::invalid(
+                "wealth_factor",
+                format!("should be a number, got {}", self.wealth_factor),
+            ));
+        }
+
+        if !self.sex_ratio_at_birth.is_finite() || self.sex_ratio_at_birth <= 0.0 {
+            errors.push(SimulationError::invalid(
+                "sex_ratio_at_birth",
+                format!("should be above 0, got {}", self.sex_ratio_at_birth),
+            ));
+        }
+
+        if let Some(schedule) = &self.fertility_schedule {
+            if schedule
+                .get_weights()
+                .iter()
+                .any(|weight| !weight.is_finite() || *weight < 0.0)
+            {
+                errors.push(SimulationError::invalid(
+                    "fertility_schedule",
+                    "should only have positive weights",
+                ));
+            }
+        }
+
+        // a high enough fertility rate can make the chance of giving birth in a year more than 1
+
+        if errors
+            .iter()
+            .all(|error| !error.field.starts_with("fertility"))
+        {
+            let rates = self.fertility_rates();
+
+            if let Some(age) = rates.iter().position(|&rate| rate > 1.0) {
+                errors.push(SimulationError::invalid(
+                    "fertility_rate",
+                    format!(
+                        "is too high, women would have {:.2} children a year at age {}",
+                        rates[age], age
+                    ),
+                ));
+            }
+        }
+
+        errors
+    }
+
+    /// The settings in effect in a year, after applying every policy.
+    pub fn at_year(&self, year: u32) -> Settings {
+        effective_settings(self, &self.policies, year)
+    }
+
+    fn add_schedule(&mut self, parameter: &str, schedule: Schedule) -> Result<(), String> {
+        let parameter = Parameter::from_name(parameter)
+            .ok_or_else(|| invalid_parameters(format!("unknown parameter {}", parameter)))?;
+
+        self.add_policy(Policy::new(parameter, schedule));
+
+        Ok(())
+    }
+}
+
+/// The oldest `max_age` allowed. Both engines keep track of every age up to `max_age`.
+pub const MAX_AGE: u32 = 1000;
+
+pub(crate) fn invalid_parameters(reason: impl std::fmt::Display) -> String {
+    format!("{}: {}", String::from(ErrorCode::InvalidParameters), reason)
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::new()
+    }
 }
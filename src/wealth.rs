@@ -0,0 +1,209 @@
+//! Wealth and mortality for Beanreadings. Poorer people die more often of infections,
+//! malnutrition and heart disease, and are less likely to get treatment when they do get sick.
+//!
+//! Each bean has a wealth between 0 (the poorest) and 1 (the richest). For the causes that wealth
+//! affects, the poorest beans have `relative risk` times the risk of the richest, with a smooth
+//! (log-linear) gradient in between. On top of that, the chance of getting treatment goes from
+//! `minimum_care_access` for the poorest to 1 for the richest, and treatment takes
+//! `treatment_effect` off the chance of dying of a treatable cause.
+//!
+//! The defaults come from:
+//! - [Mackenbach et al. 2008, Socioeconomic Inequalities in Health in 22 European Countries](https://www.nejm.org/doi/full/10.1056/NEJMsa0707519)
+//!   cardiovascular mortality is around 1.5 times higher in the lowest education group.
+//! - [Chetty et al. 2016, The Association Between Income and Life Expectancy in the United States](https://jamanetwork.com/journals/jama/fullarticle/2513561)
+//!   the richest 1% of men live 14.6 years longer than the poorest 1%.
+//! - [WHO Malnutrition Fact Sheet](https://www.who.int/news-room/fact-sheets/detail/malnutrition)
+//!   and [Our World in Data, Causes of Death](https://ourworldindata.org/causes-of-death),
+//!   deaths from malnutrition and infections are concentrated among the poorest.
+
+use crate::causes::CauseOfDeath;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// How many wealth groups results are split into.
+pub const QUINTILES: usize = 5;
+
+/// Causes that treatment can prevent deaths from.
+const TREATABLE: [CauseOfDeath; 6] = [
+    CauseOfDeath::CardiovascularDisease,
+    CauseOfDeath::Cancer,
+    CauseOfDeath::Infection,
+    CauseOfDeath::Diabetes,
+    CauseOfDeath::DiarrhealDisease,
+    CauseOfDeath::KidneyDisease,
+];
+
+/// How wealth changes the risk of dying. The relative risks compare the poorest beans to the
+/// richest, so 1.0 means wealth makes no difference.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WealthEffects {
+    pub infection: f64,
+
+    pub malnutrition: f64,
+
+    pub cardiovascular_disease: f64,
+
+    pub minimum_care_access: f64, // chance of the poorest getting treatment, the richest always do
+
+    pub treatment_effect: f64, // how much of the risk of a treatable cause treatment takes away
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl WealthEffects {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> WealthEffects {
+        WealthEffects {
+            infection: 2.0,
+            malnutrition: 3.0,
+            cardiovascular_disease: 1.5,
+            minimum_care_access: 0.2,
+            treatment_effect: 0.3,
+        }
+    }
+
+    /// Effects that make wealth irrelevant, for comparing against a world without inequality.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn none() -> WealthEffects {
+        WealthEffects {
+            infection: 1.0,
+            malnutrition: 1.0,
+            cardiovascular_disease: 1.0,
+            minimum_care_access: 1.0,
+            treatment_effect: 0.0,
+        }
+    }
+
+    /// The chance of a bean with this wealth getting treatment.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn care_access(&self, wealth: f64) -> f64 {
+        let wealth = wealth.clamp(0.0, 1.0);
+
+        self.minimum_care_access + (1.0 - self.minimum_care_access) * wealth
+    }
+}
+
+impl WealthEffects {
+    /// Adjusts the risks of a bean for its wealth.
+    pub fn apply(&self, wealth: f64, risks: &mut [f64; CauseOfDeath::COUNT]) {
+        let poverty = 1.0 - wealth.clamp(0.0, 1.0);
+
+        let gradients = [
+            (CauseOfDeath::Infection, self.infection),
+            (CauseOfDeath::Malnutrition, self.malnutrition),
+            (
+                CauseOfDeath::CardiovascularDisease,
+                self.cardiovascular_disease,
+            ),
+        ];
+
+        for (cause, relative_risk) in gradients {
+            risks[cause.index()] *= relative_risk.powf(poverty);
+        }
+
+        let treated = 1.0 - self.treatment_effect * self.care_access(wealth);
+
+        for cause in TREATABLE {
+            risks[cause.index()] *= treated;
+        }
+    }
+}
+
+impl Default for WealthEffects {
+    fn default() -> Self {
+        WealthEffects::new()
+    }
+}
+
+/// Splits a population into wealth quintiles by rank, 0 being the poorest fifth. Returns the
+/// quintile of every bean, in the same order as `wealths`.
+pub fn quintiles(wealths: &[f64]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..wealths.len()).collect();
+    order.sort_by(|&a, &b| wealths[a].total_cmp(&wealths[b]));
+
+    let mut quintiles = vec![0; wealths.len()];
+
+    for (rank, bean) in order.into_iter().enumerate() {
+        quintiles[bean] = rank * QUINTILES / wealths.len();
+    }
+
+    quintiles
+}
+
+/// Splits groups of beans into wealth quintiles, for engines that count beans instead of
+/// simulating them. Returns the share of each group that falls into each quintile, given the
+/// wealth and number of beans in every group.
+pub fn quintile_shares(wealths: &[f64], counts: &[f64]) -> Vec<[f64; QUINTILES]> {
+    let mut order: Vec<usize> = (0..wealths.len()).collect();
+    order.sort_by(|&a, &b| wealths[a].total_cmp(&wealths[b]));
+
+    let total: f64 = counts.iter().sum();
+    let size = total / QUINTILES as f64;
+
+    let mut shares = vec![[0.0; QUINTILES]; wealths.len()];
+
+    if total <= 0.0 {
+        return shares;
+    }
+
+    let mut below = 0.0; // beans poorer than the current group
+
+    for group in order {
+        let count = counts[group];
+
+        if count > 0.0 {
+            for (quintile, share) in shares[group].iter_mut().enumerate() {
+                let start = quintile as f64 * size;
+                let overlap = (below + count).min(start + size) - below.max(start);
+
+                *share = overlap.max(0.0) / count;
+            }
+        }
+
+        below += count;
+    }
+
+    shares
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_poor_die_more() {
+        let effects = WealthEffects::new();
+
+        let mut poor = [0.01; CauseOfDeath::COUNT];
+        let mut rich = [0.01; CauseOfDeath::COUNT];
+
+        effects.apply(0.0, &mut poor);
+        effects.apply(1.0, &mut rich);
+
+        let malnutrition = CauseOfDeath::Malnutrition.index();
+
+        assert!((poor[malnutrition] / rich[malnutrition] - 3.0).abs() < 1e-9);
+        assert!(poor.iter().sum::<f64>() > rich.iter().sum::<f64>());
+
+        let mut unchanged = [0.01; CauseOfDeath::COUNT];
+        WealthEffects::none().apply(0.3, &mut unchanged);
+
+        assert_eq!(unchanged, [0.01; CauseOfDeath::COUNT]);
+    }
+
+    #[test]
+    fn test_quintiles() {
+        let wealths = [0.9, 0.1, 0.5, 0.3, 0.7, 0.2, 0.4, 0.6, 0.8, 0.0];
+
+        assert_eq!(quintiles(&wealths), vec![4, 0, 2, 1, 3, 1, 2, 3, 4, 0]);
+
+        // two equal groups, the poorer one is split between quintiles 0, 1 and half of 2
+
+        let shares = quintile_shares(&[0.8, 0.2], &[50.0, 50.0]);
+
+        assert_eq!(shares[1], [0.4, 0.4, 0.2, 0.0, 0.0]);
+        assert_eq!(shares[0], [0.0, 0.0, 0.2, 0.4, 0.4]);
+    }
+}